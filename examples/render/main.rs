@@ -0,0 +1,19 @@
+extern crate cajal;
+
+use cajal::Cajal;
+use cajal::render::{Image, Layer};
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+
+
+fn main() {
+    let out = env::args().nth(1).unwrap_or("cajal.png".to_owned());
+
+    let mut cajal = Cajal::new(2, 0.001, &[1, 2, 3, 7]);
+    cajal.grow();
+
+    let mut file = BufWriter::new(File::create(&out).unwrap());
+    Image::from_cajal(&cajal, Layer::CellType).write_png(&mut file).unwrap();
+    println!("Wrote {}", out);
+}
@@ -8,7 +8,11 @@ extern crate rayon;
 
 
 use piston_window::*;
-use cajal::{Cajal, CellType};
+use cajal::Recorder;
+use cajal::render::{self, Layer};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 use time::{SteadyTime, Duration};
 
 
@@ -30,7 +34,13 @@ fn main() {
 
     let num_pages = 2u32;
     let dimension = num_pages * PAGE_WIDTH;
-    let mut cajal = Cajal::new(num_pages, 0.001, &[1, 2, 3, 7]);
+
+    // Pass a path to record the run, so it can be replayed elsewhere
+    let log: Box<Write> = match env::args().nth(1) {
+        Some(path) => Box::new(BufWriter::new(File::create(path).unwrap())),
+        None => Box::new(io::sink()),
+    };
+    let mut cajal = Recorder::new(log, num_pages, 0.001, &[1, 2, 3, 7], true).unwrap();
 
     let window: PistonWindow = WindowSettings::new("Cajal Visualization",
                                                    [dimension * SQ_SIZE, dimension * SQ_SIZE])
@@ -53,7 +63,7 @@ fn main() {
 
             mode = match mode {
                 Mode::Grow => {
-                    let active = cajal.grow_step();
+                    let active = cajal.grow_step().unwrap().active_cells;
                     info!("GROW >>> {} ({:?})", active, mode);
                     match active {
                         0 => {
@@ -66,15 +76,16 @@ fn main() {
                 Mode::Signal => {
                     if counter >= 1 {
                         for i in (0..dimension).filter(|i| i % 2 == 0) {
-                            cajal.set_input(i, i, 63);
-                            cajal.set_input(i, dimension - i - 1, 63);
+                            cajal.set_input(i, i, 63).unwrap();
+                            cajal.set_input(i, dimension - i - 1, 63).unwrap();
                         }
 
 
                         counter = 0;
                     }
 
-                    let active = cajal.signal_step();
+                    let active = cajal.signal_step().unwrap().active_cells;
+                    cajal.flush().unwrap();
                     info!("SIGNAL >>> {} ({:?})", active, mode);
                     Mode::Signal
                 }
@@ -82,24 +93,15 @@ fn main() {
             // }
 
             clear([1.0; 4], g);
+            let cajal = cajal.cajal();
 
             for x in 0u32..dimension {
                 for y in 0u32..dimension {
-                    let mut color = match (*cajal.get_cell(x, y)).get_cell_type() {
-                        CellType::Axon => color::hex("F25F5C"), // red
-                        CellType::Dendrite => color::hex("70C1B3"), // blue
-                        CellType::Body => color::hex("50514F"), // brown
-                        CellType::Empty => [1.0, 1.0, 1.0, 1.0], // white
-                    };
-
-                    if (*cajal.get_cell(x, y)).get_signal() >
-                       (*cajal.get_cell(x, y)).get_threshold() {
-                        color = color::hex("F9C22E");   // yellow
-                    } else if (*cajal.get_cell(x, y)).get_signal() > 0 {
-                        color = color::hex("FAEBC3");
-                    }
-
-
+                    let rgb = render::color(cajal.get_cell(x, y), Layer::CellType);
+                    let color = [rgb[0] as f32 / 255.0,
+                                 rgb[1] as f32 / 255.0,
+                                 rgb[2] as f32 / 255.0,
+                                 1.0];
 
                     rectangle(color,
                               [1.0, 1.0, SQ_SIZE as f64, SQ_SIZE as f64],
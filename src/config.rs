@@ -0,0 +1,696 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use num::FromPrimitive;
+use toml;
+
+use super::{Cajal, CajalError};
+use grid::{Gate, Neighbourhood};
+use snapshot::{self, Snapshot};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "failed to read config: {}", e),
+            ConfigError::Parse(ref msg) => write!(f, "failed to parse config: {}", msg),
+            ConfigError::Invalid(ref msg) => write!(f, "invalid config: {}", msg),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Io(ref e) => e.description(),
+            ConfigError::Parse(ref msg) | ConfigError::Invalid(ref msg) => msg,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+
+enum_from_primitive! {
+    // What happens to growth and signals that run off the edge of the grid
+    #[derive(Debug, PartialEq, Copy, Clone)]
+    pub enum Boundary {
+        Absorbing = 0,
+        Toroidal = 1,
+        Reflecting = 2
+    }
+}
+
+enum_from_primitive! {
+    // Hebbian plasticity follows the activity of the receiving cell at each delivery,
+    // STDP the timing of deliveries relative to threshold crossings of the neuron
+    #[derive(Debug, PartialEq, Copy, Clone)]
+    pub enum Plasticity {
+        Off = 0,
+        Hebbian = 1,
+        Stdp = 2
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CajalConfig {
+    pub size: u32,
+    // Page counts along each axis, overriding `size` to build a rectangular grid
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    // Page count along z, only 3D grids have more than one layer of pages
+    pub depth: Option<u32>,
+    pub density: f32,
+    pub seed: Vec<usize>,
+    // Thresholds are drawn uniformly from [min, max)
+    pub threshold_min: u8,
+    pub threshold_max: u8,
+    pub stim_ratio: f32,
+    // Signal strength of a neuron is drawn uniformly from [min, max), everything it grows
+    // inherits it
+    pub strength_min: u8,
+    pub strength_max: u8,
+    // Relative weights indexed by the chromosome (16, 64 in 3D) and gate (4, 6 in 3D) bit
+    // patterns.  When unset, every value is drawn from its default `Rand` distribution
+    pub chromosome_weights: Option<Vec<u32>>,
+    pub gate_weights: Option<Vec<u32>>,
+    // Bodies are never placed closer than this to a page edge
+    pub body_margin: u32,
+    pub boundary: Boundary,
+    // Events needed to raise or lower the potentiation of an axon, see `Plasticity`.
+    // 0 disables either, but not both
+    pub ltp_steps: u8,
+    pub ltd_steps: u8,
+    pub plasticity: Plasticity,
+    // Parameters of the default neuron model, see `CoDi`.  Custom models ignore them.
+    // Signal a body below its threshold loses every step, 0 disables leaking
+    pub leak: u8,
+    // Signal steps a body ignores input for after firing, 0 disables the refractory period
+    pub refractory: u8,
+    // Most signal steps between a delivery and a threshold crossing that still pair up
+    pub stdp_window: u32,
+}
+
+impl Default for CajalConfig {
+    fn default() -> CajalConfig {
+        CajalConfig {
+            size: 10,
+            width: None,
+            height: None,
+            depth: None,
+            density: 0.05,
+            seed: vec![1, 2, 3, 4],
+            threshold_min: 0,
+            threshold_max: 4,
+            stim_ratio: 0.5,
+            strength_min: 1,
+            strength_max: 16,
+            chromosome_weights: None,
+            gate_weights: None,
+            body_margin: 1,
+            boundary: Boundary::Absorbing,
+            ltp_steps: 2,
+            ltd_steps: 2,
+            plasticity: Plasticity::Off,
+            stdp_window: 5,
+            leak: 0,
+            refractory: 0,
+        }
+    }
+}
+
+impl CajalConfig {
+    pub fn from_toml(s: &str) -> Result<CajalConfig, ConfigError> {
+        let mut parser = toml::Parser::new(s);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let msg = parser.errors
+                                .iter()
+                                .map(|e| {
+                                    let (line, col) = parser.to_linecol(e.lo);
+                                    format!("{} at line {} column {}", e.desc, line + 1, col + 1)
+                                })
+                                .collect::<Vec<String>>()
+                                .join(", ");
+                return Err(ConfigError::Parse(msg));
+            }
+        };
+
+        let mut config = CajalConfig::default();
+        for (key, value) in &table {
+            match key.as_str() {
+                "size" => config.size = try!(as_u32(key, value)),
+                "width" => config.width = Some(try!(as_u32(key, value))),
+                "height" => config.height = Some(try!(as_u32(key, value))),
+                "depth" => config.depth = Some(try!(as_u32(key, value))),
+                "density" => config.density = try!(as_f32(key, value)),
+                "seed" => {
+                    config.seed = try!(as_u32_vec(key, value))
+                                      .into_iter()
+                                      .map(|s| s as usize)
+                                      .collect()
+                }
+                "threshold_min" => config.threshold_min = try!(as_u8(key, value)),
+                "threshold_max" => config.threshold_max = try!(as_u8(key, value)),
+                "stim_ratio" => config.stim_ratio = try!(as_f32(key, value)),
+                "strength_min" => config.strength_min = try!(as_u8(key, value)),
+                "strength_max" => config.strength_max = try!(as_u8(key, value)),
+                "chromosome_weights" => {
+                    config.chromosome_weights = Some(try!(as_u32_vec(key, value)))
+                }
+                "gate_weights" => config.gate_weights = Some(try!(as_u32_vec(key, value))),
+                "body_margin" => config.body_margin = try!(as_u32(key, value)),
+                "boundary" => config.boundary = try!(as_boundary(key, value)),
+                "ltp_steps" => config.ltp_steps = try!(as_u8(key, value)),
+                "ltd_steps" => config.ltd_steps = try!(as_u8(key, value)),
+                "plasticity" => config.plasticity = try!(as_plasticity(key, value)),
+                "stdp_window" => config.stdp_window = try!(as_u32(key, value)),
+                "leak" => config.leak = try!(as_u8(key, value)),
+                "refractory" => config.refractory = try!(as_u8(key, value)),
+                _ => return Err(ConfigError::Invalid(format!("unknown key `{}`", key))),
+            }
+        }
+
+        try!(config.validate());
+        Ok(config)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<CajalConfig, ConfigError> {
+        let mut s = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut s));
+        CajalConfig::from_toml(&s)
+    }
+
+    pub fn pages_x(&self) -> u32 {
+        self.width.unwrap_or(self.size)
+    }
+
+    pub fn pages_y(&self) -> u32 {
+        self.height.unwrap_or(self.size)
+    }
+
+    pub fn pages_z(&self) -> u32 {
+        self.depth.unwrap_or(1)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.validate_for::<Gate>()
+    }
+
+    // Validation against the page shape and directions of the given kind of grid
+    pub fn validate_for<N: Neighbourhood>(&self) -> Result<(), ConfigError> {
+        if self.pages_x() == 0 || self.pages_y() == 0 || self.pages_z() == 0 {
+            return Err(ConfigError::Invalid(format!("grid must be at least one page wide, \
+                                                     high and deep, found {}x{}x{}",
+                                                    self.pages_x(),
+                                                    self.pages_y(),
+                                                    self.pages_z())));
+        }
+        if N::page_depth() == 1 && self.pages_z() != 1 {
+            return Err(ConfigError::Invalid(format!("depth {} needs a 3D grid",
+                                                    self.pages_z())));
+        }
+        if !(self.density >= 0.0 && self.density <= 1.0) {
+            return Err(ConfigError::Invalid(format!("density {} must be within [0, 1]",
+                                                    self.density)));
+        }
+        if self.threshold_min >= self.threshold_max || self.threshold_max > 64 {
+            return Err(ConfigError::Invalid(format!("threshold range [{}, {}) must be \
+                                                     non-empty and within [0, 64]",
+                                                    self.threshold_min,
+                                                    self.threshold_max)));
+        }
+        if self.strength_min >= self.strength_max || self.strength_max > 16 {
+            return Err(ConfigError::Invalid(format!("strength range [{}, {}) must be \
+                                                     non-empty and within [0, 16]",
+                                                    self.strength_min,
+                                                    self.strength_max)));
+        }
+        if !(self.stim_ratio >= 0.0 && self.stim_ratio <= 1.0) {
+            return Err(ConfigError::Invalid(format!("stim_ratio {} must be within [0, 1]",
+                                                    self.stim_ratio)));
+        }
+        if self.body_margin == 0 || self.body_margin >= N::page_width() / 2 {
+            return Err(ConfigError::Invalid(format!("body_margin {} must be within [1, {})",
+                                                    self.body_margin,
+                                                    N::page_width() / 2)));
+        }
+        if self.ltp_steps > 4 || self.ltd_steps > 4 {
+            return Err(ConfigError::Invalid(format!("ltp_steps {} and ltd_steps {} must be \
+                                                     within [0, 4]",
+                                                    self.ltp_steps,
+                                                    self.ltd_steps)));
+        }
+        if self.plasticity != Plasticity::Off && self.ltp_steps == 0 && self.ltd_steps == 0 {
+            return Err(ConfigError::Invalid(format!("{:?} plasticity needs ltp_steps or \
+                                                     ltd_steps",
+                                                    self.plasticity)));
+        }
+        if self.stdp_window == 0 {
+            return Err(ConfigError::Invalid("stdp_window must be at least 1".to_owned()));
+        }
+        if let Some(ref w) = self.chromosome_weights {
+            try!(validate_weights("chromosome_weights", w, N::chromosomes() as usize));
+        }
+        if let Some(ref w) = self.gate_weights {
+            try!(validate_weights("gate_weights", w, N::directions().len()));
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot for CajalConfig {
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(snapshot::write_u32(writer, self.size));
+        try!(write_pages(writer, self.width));
+        try!(write_pages(writer, self.height));
+        try!(write_pages(writer, self.depth));
+        try!(snapshot::write_f32(writer, self.density));
+        try!(snapshot::write_u32(writer, self.seed.len() as u32));
+        for s in &self.seed {
+            try!(snapshot::write_u64(writer, *s as u64));
+        }
+        try!(snapshot::write_u8(writer, self.threshold_min));
+        try!(snapshot::write_u8(writer, self.threshold_max));
+        try!(snapshot::write_f32(writer, self.stim_ratio));
+        try!(snapshot::write_u8(writer, self.strength_min));
+        try!(snapshot::write_u8(writer, self.strength_max));
+        try!(write_weights(writer, &self.chromosome_weights));
+        try!(write_weights(writer, &self.gate_weights));
+        try!(snapshot::write_u32(writer, self.body_margin));
+        try!(self.boundary.save(writer));
+        try!(snapshot::write_u8(writer, self.ltp_steps));
+        try!(snapshot::write_u8(writer, self.ltd_steps));
+        try!(self.plasticity.save(writer));
+        try!(snapshot::write_u32(writer, self.stdp_window));
+        try!(snapshot::write_u8(writer, self.leak));
+        snapshot::write_u8(writer, self.refractory)
+    }
+
+    fn load<R: Read>(reader: &mut R) -> io::Result<CajalConfig> {
+        let size = try!(snapshot::read_u32(reader));
+        let width = try!(read_pages(reader));
+        let height = try!(read_pages(reader));
+        let depth = try!(read_pages(reader));
+        let density = try!(snapshot::read_f32(reader));
+        let len = try!(snapshot::read_u32(reader));
+        let mut seed = Vec::with_capacity(snapshot::capacity(len));
+        for _ in 0..len {
+            seed.push(try!(snapshot::read_u64(reader)) as usize);
+        }
+
+        Ok(CajalConfig {
+            size: size,
+            width: width,
+            height: height,
+            depth: depth,
+            density: density,
+            seed: seed,
+            threshold_min: try!(snapshot::read_u8(reader)),
+            threshold_max: try!(snapshot::read_u8(reader)),
+            stim_ratio: try!(snapshot::read_f32(reader)),
+            strength_min: try!(snapshot::read_u8(reader)),
+            strength_max: try!(snapshot::read_u8(reader)),
+            chromosome_weights: try!(read_weights(reader)),
+            gate_weights: try!(read_weights(reader)),
+            body_margin: try!(snapshot::read_u32(reader)),
+            boundary: try!(Boundary::load(reader)),
+            ltp_steps: try!(snapshot::read_u8(reader)),
+            ltd_steps: try!(snapshot::read_u8(reader)),
+            plasticity: try!(Plasticity::load(reader)),
+            stdp_window: try!(snapshot::read_u32(reader)),
+            leak: try!(snapshot::read_u8(reader)),
+            refractory: try!(snapshot::read_u8(reader)),
+        })
+    }
+}
+
+impl Snapshot for Boundary {
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        snapshot::write_u8(writer, *self as u8)
+    }
+
+    fn load<R: Read>(reader: &mut R) -> io::Result<Boundary> {
+        match Boundary::from_u8(try!(snapshot::read_u8(reader))) {
+            Some(b) => Ok(b),
+            None => Err(snapshot::invalid_data("invalid boundary")),
+        }
+    }
+}
+
+impl Snapshot for Plasticity {
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        snapshot::write_u8(writer, *self as u8)
+    }
+
+    fn load<R: Read>(reader: &mut R) -> io::Result<Plasticity> {
+        match Plasticity::from_u8(try!(snapshot::read_u8(reader))) {
+            Some(p) => Ok(p),
+            None => Err(snapshot::invalid_data("invalid plasticity")),
+        }
+    }
+}
+
+fn write_pages<W: Write>(writer: &mut W, pages: Option<u32>) -> io::Result<()> {
+    match pages {
+        Some(p) => {
+            try!(snapshot::write_bool(writer, true));
+            snapshot::write_u32(writer, p)
+        }
+        None => snapshot::write_bool(writer, false),
+    }
+}
+
+fn read_pages<R: Read>(reader: &mut R) -> io::Result<Option<u32>> {
+    match try!(snapshot::read_bool(reader)) {
+        true => Ok(Some(try!(snapshot::read_u32(reader)))),
+        false => Ok(None),
+    }
+}
+
+fn write_weights<W: Write>(writer: &mut W, weights: &Option<Vec<u32>>) -> io::Result<()> {
+    match *weights {
+        Some(ref w) => {
+            try!(snapshot::write_bool(writer, true));
+            snapshot::write_u32_slice(writer, w)
+        }
+        None => snapshot::write_bool(writer, false),
+    }
+}
+
+fn read_weights<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u32>>> {
+    match try!(snapshot::read_bool(reader)) {
+        true => Ok(Some(try!(snapshot::read_u32_vec(reader)))),
+        false => Ok(None),
+    }
+}
+
+fn validate_weights(key: &str, weights: &[u32], len: usize) -> Result<(), ConfigError> {
+    if weights.len() != len {
+        return Err(ConfigError::Invalid(format!("{} needs exactly {} entries, found {}",
+                                                key,
+                                                len,
+                                                weights.len())));
+    }
+    if weights.iter().all(|w| *w == 0) {
+        return Err(ConfigError::Invalid(format!("{} must not all be zero", key)));
+    }
+    Ok(())
+}
+
+fn as_u32(key: &str, value: &toml::Value) -> Result<u32, ConfigError> {
+    match value.as_integer() {
+        Some(i) if i >= 0 && i <= ::std::u32::MAX as i64 => Ok(i as u32),
+        _ => Err(ConfigError::Invalid(format!("`{}` must be a non-negative integer", key))),
+    }
+}
+
+fn as_u8(key: &str, value: &toml::Value) -> Result<u8, ConfigError> {
+    match value.as_integer() {
+        Some(i) if i >= 0 && i <= ::std::u8::MAX as i64 => Ok(i as u8),
+        _ => Err(ConfigError::Invalid(format!("`{}` must be an integer within [0, 255]", key))),
+    }
+}
+
+fn as_f32(key: &str, value: &toml::Value) -> Result<f32, ConfigError> {
+    match *value {
+        toml::Value::Float(f) => Ok(f as f32),
+        toml::Value::Integer(i) => Ok(i as f32),
+        _ => Err(ConfigError::Invalid(format!("`{}` must be a number", key))),
+    }
+}
+
+fn as_boundary(key: &str, value: &toml::Value) -> Result<Boundary, ConfigError> {
+    match value.as_str() {
+        Some("absorbing") => Ok(Boundary::Absorbing),
+        Some("toroidal") => Ok(Boundary::Toroidal),
+        Some("reflecting") => Ok(Boundary::Reflecting),
+        _ => {
+            Err(ConfigError::Invalid(format!("`{}` must be one of \"absorbing\", \"toroidal\" \
+                                              or \"reflecting\"",
+                                             key)))
+        }
+    }
+}
+
+fn as_plasticity(key: &str, value: &toml::Value) -> Result<Plasticity, ConfigError> {
+    match value.as_str() {
+        Some("off") => Ok(Plasticity::Off),
+        Some("hebbian") => Ok(Plasticity::Hebbian),
+        Some("stdp") => Ok(Plasticity::Stdp),
+        _ => {
+            Err(ConfigError::Invalid(format!("`{}` must be \"off\", \"hebbian\" or \"stdp\"",
+                                             key)))
+        }
+    }
+}
+
+fn as_u32_vec(key: &str, value: &toml::Value) -> Result<Vec<u32>, ConfigError> {
+    match value.as_slice() {
+        Some(values) => values.iter().map(|v| as_u32(key, v)).collect(),
+        None => Err(ConfigError::Invalid(format!("`{}` must be an array of integers", key))),
+    }
+}
+
+
+pub struct CajalBuilder {
+    config: CajalConfig,
+}
+
+impl Default for CajalBuilder {
+    fn default() -> CajalBuilder {
+        CajalBuilder::new()
+    }
+}
+
+impl CajalBuilder {
+    pub fn new() -> CajalBuilder {
+        CajalBuilder { config: CajalConfig::default() }
+    }
+
+    pub fn from_config(config: CajalConfig) -> CajalBuilder {
+        CajalBuilder { config: config }
+    }
+
+    pub fn size(mut self, size: u32) -> CajalBuilder {
+        self.config.size = size;
+        self
+    }
+
+    pub fn dimensions(mut self, width: u32, height: u32) -> CajalBuilder {
+        self.config.width = Some(width);
+        self.config.height = Some(height);
+        self
+    }
+
+    pub fn density(mut self, density: f32) -> CajalBuilder {
+        self.config.density = density;
+        self
+    }
+
+    pub fn seed(mut self, seed: &[usize]) -> CajalBuilder {
+        self.config.seed = seed.to_vec();
+        self
+    }
+
+    pub fn threshold_range(mut self, min: u8, max: u8) -> CajalBuilder {
+        self.config.threshold_min = min;
+        self.config.threshold_max = max;
+        self
+    }
+
+    pub fn strength_range(mut self, min: u8, max: u8) -> CajalBuilder {
+        self.config.strength_min = min;
+        self.config.strength_max = max;
+        self
+    }
+
+    pub fn stim_ratio(mut self, ratio: f32) -> CajalBuilder {
+        self.config.stim_ratio = ratio;
+        self
+    }
+
+    pub fn chromosome_weights(mut self, weights: &[u32]) -> CajalBuilder {
+        self.config.chromosome_weights = Some(weights.to_vec());
+        self
+    }
+
+    pub fn gate_weights(mut self, weights: &[u32]) -> CajalBuilder {
+        self.config.gate_weights = Some(weights.to_vec());
+        self
+    }
+
+    pub fn body_margin(mut self, margin: u32) -> CajalBuilder {
+        self.config.body_margin = margin;
+        self
+    }
+
+    pub fn boundary(mut self, boundary: Boundary) -> CajalBuilder {
+        self.config.boundary = boundary;
+        self
+    }
+
+    pub fn potentiation(mut self, ltp_steps: u8, ltd_steps: u8) -> CajalBuilder {
+        self.config.ltp_steps = ltp_steps;
+        self.config.ltd_steps = ltd_steps;
+        self
+    }
+
+    pub fn plasticity(mut self, plasticity: Plasticity) -> CajalBuilder {
+        self.config.plasticity = plasticity;
+        self
+    }
+
+    pub fn stdp(mut self, window: u32) -> CajalBuilder {
+        self.config.plasticity = Plasticity::Stdp;
+        self.config.stdp_window = window;
+        self
+    }
+
+    pub fn leak(mut self, leak: u8) -> CajalBuilder {
+        self.config.leak = leak;
+        self
+    }
+
+    pub fn refractory(mut self, steps: u8) -> CajalBuilder {
+        self.config.refractory = steps;
+        self
+    }
+
+    pub fn config(&self) -> &CajalConfig {
+        &self.config
+    }
+
+    pub fn build(self) -> Result<Cajal, CajalError> {
+        Cajal::try_with_config(&self.config)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::{Boundary, CajalBuilder, CajalConfig, ConfigError};
+    use snapshot::Snapshot;
+
+    #[test]
+    fn toml_overrides_defaults() {
+        let config = CajalConfig::from_toml(r#"
+            size = 2
+            density = 0.01
+            seed = [5, 6, 7]
+            threshold_min = 1
+            threshold_max = 8
+            gate_weights = [1, 0, 1, 0]
+            boundary = "toroidal"
+        "#)
+                         .unwrap();
+
+        assert!(config.size == 2);
+        assert!(config.density == 0.01);
+        assert!(config.seed == vec![5, 6, 7]);
+        assert!(config.threshold_min == 1 && config.threshold_max == 8);
+        assert!(config.gate_weights == Some(vec![1, 0, 1, 0]));
+        assert!(config.boundary == Boundary::Toroidal);
+        assert!(config.stim_ratio == CajalConfig::default().stim_ratio);
+        assert!(config.chromosome_weights.is_none());
+    }
+
+    #[test]
+    fn example_config() {
+        let config = CajalConfig::from_toml(include_str!("../examples/config.toml")).unwrap();
+        assert!(config == CajalConfig::default());
+    }
+
+    #[test]
+    fn toml_errors() {
+        match CajalConfig::from_toml("size = ") {
+            Err(ConfigError::Parse(_)) => {}
+            _ => panic!("expected a parse error"),
+        }
+        match CajalConfig::from_toml("sise = 2") {
+            Err(ConfigError::Invalid(_)) => {}
+            _ => panic!("expected an unknown key error"),
+        }
+        match CajalConfig::from_toml("threshold_min = 4\nthreshold_max = 4") {
+            Err(ConfigError::Invalid(_)) => {}
+            _ => panic!("expected an invalid threshold range"),
+        }
+        match CajalConfig::from_toml("boundary = \"open\"") {
+            Err(ConfigError::Invalid(_)) => {}
+            _ => panic!("expected an invalid boundary"),
+        }
+        match CajalConfig::from_toml("gate_weights = [1, 2]") {
+            Err(ConfigError::Invalid(_)) => {}
+            _ => panic!("expected invalid gate weights"),
+        }
+        match CajalConfig::from_toml("plasticity = \"hebbian\"\nltp_steps = 0\nltd_steps = 0") {
+            Err(ConfigError::Invalid(_)) => {}
+            _ => panic!("expected plasticity that never changes anything"),
+        }
+    }
+
+    #[test]
+    fn config_snapshot() {
+        let config = CajalBuilder::new()
+                         .size(3)
+                         .dimensions(4, 1)
+                         .chromosome_weights(&[1; 16])
+                         .boundary(Boundary::Reflecting)
+                         .potentiation(2, 3)
+                         .stdp(8)
+                         .leak(2)
+                         .refractory(3)
+                         .config()
+                         .clone();
+
+        let mut buf = Vec::new();
+        config.save(&mut buf).unwrap();
+        assert!(CajalConfig::load(&mut Cursor::new(buf)).unwrap() == config);
+    }
+
+    #[test]
+    fn builder_applies_config() {
+        let cajal = CajalBuilder::new()
+                        .size(1)
+                        .density(0.01)
+                        .threshold_range(5, 6)
+                        .strength_range(9, 10)
+                        .stim_ratio(1.0)
+                        .gate_weights(&[0, 0, 0, 1])
+                        .build()
+                        .unwrap();
+
+        for x in 0..256 {
+            for y in 0..256 {
+                let cell = cajal.get_cell(x, y);
+                assert!(cell.get_threshold() == 5);
+                match cell.get_cell_type() {
+                    ::CellType::Body => assert!(cell.get_stim() == true),
+                    ::CellType::Empty => assert!(cell.get_gate() == ::Gate::East),
+                    _ => {}
+                }
+                match cell.get_cell_type() {
+                    ::CellType::Empty => assert!(cell.get_strength() == 0),
+                    _ => assert!(cell.get_strength() == 9),
+                }
+            }
+        }
+
+        assert!(CajalBuilder::new().stim_ratio(2.0).build().is_err());
+    }
+}
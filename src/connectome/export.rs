@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use rustc_serialize::json::{Json, ToJson};
+
+use super::{Connectome, Neuron, Synapse};
+
+impl ToJson for Neuron {
+    fn to_json(&self) -> Json {
+        let mut node = BTreeMap::new();
+        node.insert("id".to_owned(), self.id.to_json());
+        node.insert("x".to_owned(), self.x.to_json());
+        node.insert("y".to_owned(), self.y.to_json());
+        node.insert("stim".to_owned(), self.stim.to_json());
+        node.insert("threshold".to_owned(), self.threshold.to_json());
+        node.insert("axon_cells".to_owned(), self.axon_cells.to_json());
+        node.insert("dendrite_cells".to_owned(), self.dendrite_cells.to_json());
+        Json::Object(node)
+    }
+}
+
+impl ToJson for Synapse {
+    fn to_json(&self) -> Json {
+        let mut edge = BTreeMap::new();
+        edge.insert("source".to_owned(), self.from.to_json());
+        edge.insert("target".to_owned(), self.to.to_json());
+        edge.insert("stim".to_owned(), self.stim.to_json());
+        edge.insert("weight".to_owned(), self.contacts.to_json());
+        Json::Object(edge)
+    }
+}
+
+// Laid out as a node-link graph, which networkx reads with `json_graph.node_link_graph`
+impl ToJson for Connectome {
+    fn to_json(&self) -> Json {
+        let mut graph = BTreeMap::new();
+        graph.insert("directed".to_owned(), true.to_json());
+        graph.insert("multigraph".to_owned(), false.to_json());
+        graph.insert("graph".to_owned(), Json::Object(BTreeMap::new()));
+        graph.insert("nodes".to_owned(), self.neurons.to_json());
+        graph.insert("links".to_owned(), self.synapses.to_json());
+        Json::Object(graph)
+    }
+}
+
+impl Connectome {
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "{}\n", self.to_json().pretty())
+    }
+
+    pub fn write_dot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(writeln!(writer, "digraph connectome {{"));
+        for n in &self.neurons {
+            try!(writeln!(writer,
+                          "    {} [pos=\"{},{}!\", x={}, y={}, stim={}, threshold={}];",
+                          n.id,
+                          n.x,
+                          n.y,
+                          n.x,
+                          n.y,
+                          n.stim,
+                          n.threshold));
+        }
+        for s in &self.synapses {
+            try!(writeln!(writer,
+                          "    {} -> {} [stim={}, weight={}, color={}];",
+                          s.from,
+                          s.to,
+                          s.stim,
+                          s.contacts,
+                          if s.stim { "black" } else { "red" }));
+        }
+        writeln!(writer, "}}")
+    }
+
+    pub fn write_graphml<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        try!(writeln!(writer,
+                      "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"));
+        try!(writeln!(writer,
+                      "  <key id=\"x\" for=\"node\" attr.name=\"x\" attr.type=\"int\"/>"));
+        try!(writeln!(writer,
+                      "  <key id=\"y\" for=\"node\" attr.name=\"y\" attr.type=\"int\"/>"));
+        try!(writeln!(writer,
+                      "  <key id=\"threshold\" for=\"node\" attr.name=\"threshold\" \
+                       attr.type=\"int\"/>"));
+        try!(writeln!(writer,
+                      "  <key id=\"stim\" for=\"all\" attr.name=\"stim\" \
+                       attr.type=\"boolean\"/>"));
+        try!(writeln!(writer,
+                      "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" \
+                       attr.type=\"int\"/>"));
+        try!(writeln!(writer, "  <graph id=\"connectome\" edgedefault=\"directed\">"));
+
+        for n in &self.neurons {
+            try!(writeln!(writer, "    <node id=\"n{}\">", n.id));
+            try!(writeln!(writer, "      <data key=\"x\">{}</data>", n.x));
+            try!(writeln!(writer, "      <data key=\"y\">{}</data>", n.y));
+            try!(writeln!(writer, "      <data key=\"stim\">{}</data>", n.stim));
+            try!(writeln!(writer,
+                          "      <data key=\"threshold\">{}</data>",
+                          n.threshold));
+            try!(writeln!(writer, "    </node>"));
+        }
+        for (i, s) in self.synapses.iter().enumerate() {
+            try!(writeln!(writer,
+                          "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">",
+                          i,
+                          s.from,
+                          s.to));
+            try!(writeln!(writer, "      <data key=\"stim\">{}</data>", s.stim));
+            try!(writeln!(writer, "      <data key=\"weight\">{}</data>", s.contacts));
+            try!(writeln!(writer, "    </edge>"));
+        }
+
+        try!(writeln!(writer, "  </graph>"));
+        writeln!(writer, "</graphml>")
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::str;
+    use rustc_serialize::json::Json;
+    use super::super::{Connectome, Neuron, Synapse};
+
+    fn connectome() -> Connectome {
+        let neuron = |id, x, y, stim| {
+            Neuron {
+                id: id,
+                x: x,
+                y: y,
+                stim: stim,
+                threshold: 2,
+                axon_cells: 5,
+                dendrite_cells: 3,
+            }
+        };
+
+        Connectome {
+            neurons: vec![neuron(0, 10, 20, true), neuron(1, 30, 40, false)],
+            synapses: vec![Synapse {
+                               from: 0,
+                               to: 1,
+                               stim: true,
+                               contacts: 3,
+                           },
+                           Synapse {
+                               from: 1,
+                               to: 0,
+                               stim: false,
+                               contacts: 1,
+                           }],
+        }
+    }
+
+    #[test]
+    fn json() {
+        let mut buf = Vec::new();
+        connectome().write_json(&mut buf).unwrap();
+
+        let json = Json::from_str(str::from_utf8(&buf).unwrap()).unwrap();
+        let nodes = json.find("nodes").unwrap().as_array().unwrap();
+        assert!(nodes.len() == 2);
+        assert!(nodes[1].find("x").unwrap().as_u64() == Some(30));
+
+        let links = json.find("links").unwrap().as_array().unwrap();
+        assert!(links.len() == 2);
+        assert!(links[0].find("weight").unwrap().as_u64() == Some(3));
+        assert!(links[1].find("stim").unwrap().as_boolean() == Some(false));
+    }
+
+    #[test]
+    fn dot() {
+        let mut buf = Vec::new();
+        connectome().write_dot(&mut buf).unwrap();
+        let dot = str::from_utf8(&buf).unwrap();
+
+        assert!(dot.starts_with("digraph connectome {"));
+        assert!(dot.contains("0 [pos=\"10,20!\", x=10, y=20, stim=true, threshold=2];"));
+        assert!(dot.contains("1 -> 0 [stim=false, weight=1, color=red];"));
+        assert!(dot.trim_right().ends_with("}"));
+    }
+
+    #[test]
+    fn graphml() {
+        let mut buf = Vec::new();
+        connectome().write_graphml(&mut buf).unwrap();
+        let xml = str::from_utf8(&buf).unwrap();
+
+        assert!(xml.matches("<node ").count() == 2);
+        assert!(xml.matches("<edge ").count() == 2);
+        assert!(xml.contains("<edge id=\"e0\" source=\"n0\" target=\"n1\">"));
+        assert!(xml.trim_right().ends_with("</graphml>"));
+    }
+}
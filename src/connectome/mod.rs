@@ -0,0 +1,238 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use grid::{Cell, CellType, Gate};
+use super::Cajal;
+
+mod export;
+
+static CARDINAL_DIRECTIONS: &'static [Gate] = &[Gate::North, Gate::South, Gate::East, Gate::West];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neuron {
+    pub id: usize,
+    pub x: u32,
+    pub y: u32,
+    pub stim: bool,
+    pub threshold: u8,
+    pub axon_cells: u32,
+    pub dendrite_cells: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Synapse {
+    pub from: usize,
+    pub to: usize,
+    pub stim: bool,
+    pub contacts: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Connectome {
+    neurons: Vec<Neuron>,
+    synapses: Vec<Synapse>,
+}
+
+impl Connectome {
+    pub fn extract(cajal: &Cajal) -> Connectome {
+        let width = cajal.width();
+        let key = |x: u32, y: u32| y as u64 * width as u64 + x as u64;
+
+        let mut neurons = Vec::new();
+        for y in 0..cajal.height() {
+            for x in 0..width {
+                let cell = cajal.get_cell(x, y);
+                if cell.get_cell_type() == CellType::Body {
+                    neurons.push(Neuron {
+                        id: neurons.len(),
+                        x: x,
+                        y: y,
+                        stim: cell.get_stim(),
+                        threshold: cell.get_threshold(),
+                        axon_cells: 0,
+                        dendrite_cells: 0,
+                    });
+                }
+            }
+        }
+        debug!("Found {} neurons", neurons.len());
+
+        // Walk each tree outwards from its body.  Every grown cell's gate points back at
+        // the cell it grew from, so a neighbour is a child if its gate points at us
+        let mut owners: HashMap<u64, usize> = HashMap::new();
+        let mut axons: Vec<(u32, u32, usize)> = Vec::new();
+        let mut queue = VecDeque::new();
+
+        for neuron in &mut neurons {
+            owners.insert(key(neuron.x, neuron.y), neuron.id);
+            queue.push_back((neuron.x, neuron.y));
+
+            while let Some((x, y)) = queue.pop_front() {
+                let parent_type = cajal.get_cell(x, y).get_cell_type();
+
+                for direction in CARDINAL_DIRECTIONS {
+                    let (nx, ny) = match cajal.grid.neighbour(x, y, *direction) {
+                        Some(n) => n,
+                        None => continue,
+                    };
+                    let child = cajal.get_cell(nx, ny);
+                    if !is_child(parent_type, child) || child.get_gate() != !*direction ||
+                       owners.contains_key(&key(nx, ny)) {
+                        continue;
+                    }
+
+                    owners.insert(key(nx, ny), neuron.id);
+                    queue.push_back((nx, ny));
+                    match child.get_cell_type() {
+                        CellType::Axon => {
+                            neuron.axon_cells += 1;
+                            axons.push((nx, ny, neuron.id));
+                        }
+                        _ => neuron.dendrite_cells += 1,
+                    }
+                }
+            }
+        }
+        debug!("Assigned {} cells to neurons", owners.len());
+
+        // An axon cell touching another neuron's dendrite or body forms a synapse
+        let mut contacts: BTreeMap<(usize, usize), u32> = BTreeMap::new();
+        for &(x, y, from) in &axons {
+            for direction in CARDINAL_DIRECTIONS {
+                let (nx, ny) = match cajal.grid.neighbour(x, y, *direction) {
+                    Some(n) => n,
+                    None => continue,
+                };
+                match cajal.get_cell(nx, ny).get_cell_type() {
+                    CellType::Dendrite | CellType::Body => {}
+                    _ => continue,
+                }
+                match owners.get(&key(nx, ny)) {
+                    Some(&to) if to != from => *contacts.entry((from, to)).or_insert(0) += 1,
+                    _ => {}
+                }
+            }
+        }
+
+        let synapses = contacts.into_iter()
+                               .map(|((from, to), contacts)| {
+                                   Synapse {
+                                       from: from,
+                                       to: to,
+                                       stim: neurons[from].stim,
+                                       contacts: contacts,
+                                   }
+                               })
+                               .collect::<Vec<Synapse>>();
+        debug!("Found {} synapses", synapses.len());
+
+        Connectome {
+            neurons: neurons,
+            synapses: synapses,
+        }
+    }
+
+    pub fn neurons(&self) -> &[Neuron] {
+        &self.neurons
+    }
+
+    pub fn synapses(&self) -> &[Synapse] {
+        &self.synapses
+    }
+
+    pub fn neuron_at(&self, x: u32, y: u32) -> Option<&Neuron> {
+        self.neurons.iter().find(|n| n.x == x && n.y == y)
+    }
+
+    pub fn outgoing(&self, id: usize) -> Vec<&Synapse> {
+        self.synapses.iter().filter(|s| s.from == id).collect()
+    }
+
+    pub fn incoming(&self, id: usize) -> Vec<&Synapse> {
+        self.synapses.iter().filter(|s| s.to == id).collect()
+    }
+}
+
+fn is_child(parent_type: CellType, child: &Cell) -> bool {
+    match (parent_type, child.get_cell_type()) {
+        (CellType::Body, CellType::Axon) |
+        (CellType::Body, CellType::Dendrite) |
+        (CellType::Axon, CellType::Axon) |
+        (CellType::Dendrite, CellType::Dendrite) => true,
+        (_, _) => false,
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::Connectome;
+    use grid::{CellType, Gate};
+    use Cajal;
+
+    fn place(cajal: &mut Cajal, x: u32, y: u32, cell_type: CellType, gate: Gate, stim: bool) {
+        let cell = cajal.grid.get_mut_cell(x, y);
+        cell.set_cell_type(cell_type);
+        cell.set_gate(gate);
+        cell.set_stim(stim);
+    }
+
+    #[test]
+    fn axon_touching_dendrite() {
+        let mut cajal = Cajal::new(2, 0.0, &[1, 2, 3, 4]);
+
+        // A: body at (250, 10) with an axon growing east across the page boundary
+        place(&mut cajal, 250, 10, CellType::Body, Gate::East, false);
+        for x in 251..258 {
+            place(&mut cajal, x, 10, CellType::Axon, Gate::West, false);
+        }
+
+        // B: body at (260, 10) with a dendrite growing west to meet the axon
+        place(&mut cajal, 260, 10, CellType::Body, Gate::West, true);
+        place(&mut cajal, 259, 10, CellType::Dendrite, Gate::East, false);
+        place(&mut cajal, 258, 10, CellType::Dendrite, Gate::East, false);
+
+        let connectome = Connectome::extract(&cajal);
+        assert!(connectome.neurons().len() == 2);
+
+        let a = connectome.neuron_at(250, 10).unwrap().clone();
+        let b = connectome.neuron_at(260, 10).unwrap().clone();
+        assert!(a.axon_cells == 7 && a.dendrite_cells == 0);
+        assert!(b.axon_cells == 0 && b.dendrite_cells == 2);
+
+        assert!(connectome.synapses().len() == 1);
+        let synapse = connectome.synapses()[0];
+        assert!(synapse.from == a.id && synapse.to == b.id);
+        assert!(synapse.stim == false);
+        assert!(synapse.contacts == 1);
+        assert!(connectome.outgoing(a.id).len() == 1);
+        assert!(connectome.incoming(a.id).is_empty());
+    }
+
+    #[test]
+    fn gate_decides_ownership() {
+        let mut cajal = Cajal::new(1, 0.0, &[1, 2, 3, 4]);
+
+        place(&mut cajal, 10, 10, CellType::Body, Gate::North, true);
+        place(&mut cajal, 10, 11, CellType::Axon, Gate::South, true);
+
+        // Touches the body, but grew from somewhere else
+        place(&mut cajal, 11, 10, CellType::Axon, Gate::East, true);
+
+        let connectome = Connectome::extract(&cajal);
+        assert!(connectome.neurons()[0].axon_cells == 1);
+        assert!(connectome.synapses().is_empty());
+    }
+
+    #[test]
+    fn grown_network() {
+        let mut cajal = Cajal::new(1, 0.001, &[1, 2, 3, 4]);
+        cajal.grow();
+
+        let connectome = Connectome::extract(&cajal);
+        assert!(connectome.neurons().len() > 0);
+        for synapse in connectome.synapses() {
+            assert!(synapse.from != synapse.to);
+            assert!(synapse.stim == connectome.neurons()[synapse.from].stim);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::VecDeque;
+
+use output::Region;
+use super::CajalError;
+
+// The signal an encoder applies to a region at each step, as pairs of a position in
+// `Region::cells` and a signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub steps: Vec<Vec<(u32, u8)>>,
+}
+
+impl Schedule {
+    pub fn new(steps: u32) -> Schedule {
+        Schedule { steps: vec![Vec::new(); steps as usize] }
+    }
+}
+
+// Turns a vector of values, nominally within [0, 1], into a schedule for a region of
+// `cells` cells
+pub trait Encoder {
+    fn encode(&self, values: &[f32], cells: usize) -> Result<Schedule, CajalError>;
+}
+
+fn clamp(value: f32) -> f32 {
+    value.max(0.0).min(1.0)
+}
+
+fn one_per_cell(values: &[f32], cells: usize) -> Result<(), CajalError> {
+    if values.len() > cells {
+        return Err(CajalError::Encoding(format!("{} values do not fit a region of {} cells",
+                                                values.len(),
+                                                cells)));
+    }
+    Ok(())
+}
+
+// Every value drives a cell of its own, which receives signal on that fraction of the
+// steps, spread out evenly
+#[derive(Debug, Copy, Clone)]
+pub struct Rate {
+    pub steps: u32,
+    pub signal: u8,
+}
+
+impl Rate {
+    pub fn new(steps: u32) -> Rate {
+        Rate {
+            steps: steps,
+            signal: 63,
+        }
+    }
+
+    fn schedule(&self, values: &[f32]) -> Schedule {
+        let mut schedule = Schedule::new(self.steps);
+        for (i, value) in values.iter().enumerate() {
+            let value = clamp(*value);
+            for step in 0..self.steps {
+                // A spike whenever the running total passes another whole number
+                if ((step + 1) as f32 * value).floor() > (step as f32 * value).floor() {
+                    schedule.steps[step as usize].push((i as u32, self.signal));
+                }
+            }
+        }
+        schedule
+    }
+}
+
+impl Encoder for Rate {
+    fn encode(&self, values: &[f32], cells: usize) -> Result<Schedule, CajalError> {
+        try!(one_per_cell(values, cells));
+        Ok(self.schedule(values))
+    }
+}
+
+// Every value drives a cell of its own with a single spike, the larger the value the
+// earlier.  Values of zero and below never spike
+#[derive(Debug, Copy, Clone)]
+pub struct Latency {
+    pub steps: u32,
+    pub signal: u8,
+}
+
+impl Latency {
+    pub fn new(steps: u32) -> Latency {
+        Latency {
+            steps: steps,
+            signal: 63,
+        }
+    }
+}
+
+impl Encoder for Latency {
+    fn encode(&self, values: &[f32], cells: usize) -> Result<Schedule, CajalError> {
+        try!(one_per_cell(values, cells));
+
+        let mut schedule = Schedule::new(self.steps);
+        if self.steps == 0 {
+            return Ok(schedule);
+        }
+        for (i, value) in values.iter().enumerate().filter(|&(_, v)| *v > 0.0) {
+            let step = ((1.0 - clamp(*value)) * (self.steps - 1) as f32).round();
+            schedule.steps[step as usize].push((i as u32, self.signal));
+        }
+        Ok(schedule)
+    }
+}
+
+// The region is split evenly between the values.  The cells of a value prefer values
+// spread across [0, 1] and are rate coded by a gaussian of the distance to their
+// preference, `width` being its standard deviation
+#[derive(Debug, Copy, Clone)]
+pub struct Population {
+    pub steps: u32,
+    pub signal: u8,
+    pub width: f32,
+}
+
+impl Population {
+    pub fn new(steps: u32) -> Population {
+        Population {
+            steps: steps,
+            signal: 63,
+            width: 0.1,
+        }
+    }
+}
+
+impl Encoder for Population {
+    fn encode(&self, values: &[f32], cells: usize) -> Result<Schedule, CajalError> {
+        try!(one_per_cell(values, cells));
+        if values.is_empty() {
+            return Ok(Schedule::new(self.steps));
+        }
+
+        let group = cells / values.len();
+        let mut activations = Vec::with_capacity(group * values.len());
+        for value in values {
+            let value = clamp(*value);
+            for j in 0..group {
+                let preferred = match group {
+                    1 => 0.5,
+                    _ => j as f32 / (group - 1) as f32,
+                };
+                let distance = value - preferred;
+                activations.push((-distance * distance / (2.0 * self.width * self.width)).exp());
+            }
+        }
+
+        let rate = Rate {
+            steps: self.steps,
+            signal: self.signal,
+        };
+        Ok(rate.schedule(&activations))
+    }
+}
+
+
+// A named region schedules are queued on, applied one step at a time as the grid signals
+#[derive(Clone)]
+pub struct InputRegion {
+    name: String,
+    region: Region,
+    cells: Vec<(u32, u32)>,
+    pending: VecDeque<Vec<(u32, u8)>>,
+}
+
+impl InputRegion {
+    pub fn new(name: &str, region: Region) -> InputRegion {
+        InputRegion {
+            name: name.to_owned(),
+            cells: region.cells(),
+            region: region,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn region(&self) -> &Region {
+        &self.region
+    }
+
+    pub fn cells(&self) -> &[(u32, u32)] {
+        &self.cells
+    }
+
+    // Steps left until everything queued has been applied
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Overlays `schedule` onto whatever is still pending, starting with the next step
+    pub fn queue(&mut self, schedule: Schedule) -> Result<(), CajalError> {
+        let cells = self.cells.len() as u32;
+        if let Some(&(position, _)) = schedule.steps
+                                              .iter()
+                                              .flat_map(|step| step.iter())
+                                              .find(|&&(position, _)| position >= cells) {
+            return Err(CajalError::Encoding(format!("position {} lies outside of region {} \
+                                                     with {} cells",
+                                                    position,
+                                                    self.name,
+                                                    cells)));
+        }
+
+        for (i, step) in schedule.steps.into_iter().enumerate() {
+            if i == self.pending.len() {
+                self.pending.push_back(Vec::new());
+            }
+            self.pending[i].extend(step);
+        }
+        Ok(())
+    }
+
+    // The cells and signals due this step
+    pub fn next(&mut self) -> Vec<(u32, u32, u8)> {
+        match self.pending.pop_front() {
+            Some(step) => {
+                step.into_iter()
+                    .map(|(position, signal)| {
+                        let (x, y) = self.cells[position as usize];
+                        (x, y, signal)
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Encoder, Latency, Population, Rate};
+    use {CajalBuilder, CajalError, Region};
+
+    #[test]
+    fn rate() {
+        let schedule = Rate::new(4).encode(&[1.0, 0.5, 0.0], 3).unwrap();
+        assert!(schedule.steps ==
+                vec![vec![(0, 63)], vec![(0, 63), (1, 63)], vec![(0, 63)], vec![(0, 63), (1, 63)]]);
+
+        match Rate::new(4).encode(&[1.0, 0.5], 1) {
+            Err(CajalError::Encoding(_)) => {}
+            _ => panic!("expected too many values"),
+        }
+    }
+
+    #[test]
+    fn latency() {
+        let schedule = Latency::new(5).encode(&[1.0, 0.5, 0.0, 0.01], 4).unwrap();
+        assert!(schedule.steps ==
+                vec![vec![(0, 63)], vec![], vec![(1, 63)], vec![], vec![(3, 63)]]);
+    }
+
+    #[test]
+    fn population() {
+        let schedule = Population::new(10).encode(&[0.0, 1.0], 6).unwrap();
+        let spikes = |position| {
+            schedule.steps.iter().filter(|step| step.iter().any(|s| s.0 == position)).count()
+        };
+        assert!(spikes(0) == 10 && spikes(2) == 0);
+        assert!(spikes(3) == 0 && spikes(5) == 10);
+        assert!(spikes(1) == 0 && spikes(4) == 0);
+    }
+
+    #[test]
+    fn applied_during_signal_step() {
+        let mut cajal = CajalBuilder::new().size(1).density(0.0).build().unwrap();
+        cajal.add_input("in", Region::Cells(vec![(10, 10), (20, 20)])).unwrap();
+        assert!(cajal.add_input("outside", Region::Cells(vec![(256, 0)])).is_err());
+        assert!(cajal.encode("missing", &Rate::new(3), &[1.0]).is_err());
+
+        cajal.encode("in", &Latency::new(3), &[0.5, 1.0]).unwrap();
+        cajal.signal_step();
+        assert!(cajal.get_cell(10, 10).get_signal() == 0);
+        assert!(cajal.get_cell(20, 20).get_signal() == 63);
+        cajal.signal_step();
+        assert!(cajal.get_cell(10, 10).get_signal() == 63);
+        assert!(cajal.inputs()[0].pending() == 1);
+    }
+}
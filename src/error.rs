@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::fmt;
+
+use config::ConfigError;
+
+#[derive(Debug)]
+pub enum CajalError {
+    OutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    OutOfBounds3D {
+        x: u32,
+        y: u32,
+        z: u32,
+        width: u32,
+        height: u32,
+        depth: u32,
+    },
+    Config(ConfigError),
+    Overflow(String),
+    Encoding(String),
+    Task(String),
+}
+
+impl fmt::Display for CajalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CajalError::OutOfBounds { x, y, width, height } => {
+                write!(f,
+                       "coordinate ({}, {}) lies outside of the {}x{} grid",
+                       x,
+                       y,
+                       width,
+                       height)
+            }
+            CajalError::OutOfBounds3D { x, y, z, width, height, depth } => {
+                write!(f,
+                       "coordinate ({}, {}, {}) lies outside of the {}x{}x{} grid",
+                       x,
+                       y,
+                       z,
+                       width,
+                       height,
+                       depth)
+            }
+            CajalError::Config(ref e) => write!(f, "{}", e),
+            CajalError::Overflow(ref msg) => write!(f, "overflow: {}", msg),
+            CajalError::Encoding(ref msg) => write!(f, "encoding: {}", msg),
+            CajalError::Task(ref msg) => write!(f, "task: {}", msg),
+        }
+    }
+}
+
+impl Error for CajalError {
+    fn description(&self) -> &str {
+        match *self {
+            CajalError::OutOfBounds { .. } |
+            CajalError::OutOfBounds3D { .. } => "coordinate lies outside of the grid",
+            CajalError::Config(ref e) => e.description(),
+            CajalError::Overflow(ref msg) |
+            CajalError::Encoding(ref msg) |
+            CajalError::Task(ref msg) => msg,
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            CajalError::Config(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for CajalError {
+    fn from(e: ConfigError) -> CajalError {
+        CajalError::Config(e)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::CajalError;
+    use {Cajal, Cajal3D, CajalBuilder, CajalConfig};
+
+    #[test]
+    fn fallible_api() {
+        match Cajal::try_new(0, 0.05, &[1, 2, 3, 4]) {
+            Err(CajalError::Config(_)) => {}
+            _ => panic!("expected an invalid size"),
+        }
+        assert!(Cajal::try_new(1, 1.5, &[1, 2, 3, 4]).is_err());
+        match CajalBuilder::new().dimensions(1 << 24, 1).build() {
+            Err(CajalError::Overflow(_)) => {}
+            _ => panic!("expected an overflow"),
+        }
+
+        let mut cajal = Cajal::try_new(1, 0.01, &[1, 2, 3, 4]).unwrap();
+        assert!(cajal.try_get_cell(255, 255).is_ok());
+        match cajal.try_get_cell(256, 0) {
+            Err(CajalError::OutOfBounds { x: 256, y: 0, .. }) => {}
+            _ => panic!("expected out of bounds"),
+        }
+        assert!(cajal.try_set_input(0, 300, 10).is_err());
+        assert!(cajal.try_set_input(10, 10, 64).is_err());
+        assert!(cajal.try_set_input(10, 10, 63).is_ok());
+
+        match Cajal3D::try_new(0, 0.05, &[1, 2, 3, 4]) {
+            Err(CajalError::Config(_)) => {}
+            _ => panic!("expected an invalid size"),
+        }
+        let config = CajalConfig { size: 1, depth: Some(1 << 28), ..CajalConfig::default() };
+        match Cajal3D::try_with_config(&config) {
+            Err(CajalError::Overflow(_)) => {}
+            _ => panic!("expected an overflow"),
+        }
+        // Chromosome weights of a flat grid don't cover the vertical directions
+        let config = CajalConfig {
+            size: 1,
+            chromosome_weights: Some(vec![1; 16]),
+            ..CajalConfig::default()
+        };
+        assert!(Cajal3D::try_with_config(&config).is_err());
+
+        let mut cajal = Cajal3D::try_new(1, 0.0, &[1, 2, 3, 4]).unwrap();
+        assert!(cajal.try_get_cell(31, 31, 31).is_ok());
+        match cajal.try_get_cell(0, 0, 32) {
+            Err(CajalError::OutOfBounds3D { z: 32, depth: 32, .. }) => {}
+            _ => panic!("expected out of bounds"),
+        }
+        assert!(cajal.try_set_input(1, 1, 1, 64).is_err());
+        assert!(cajal.try_set_input(1, 1, 1, 63).is_ok());
+    }
+}
@@ -0,0 +1,428 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use rand::{Rng, SeedableRng, StdRng};
+use rand::distributions::{IndependentSample, Range};
+use rayon::par_iter::*;
+
+use config::{CajalConfig, ConfigError};
+use grid::{self, zorder, Cell, GeneSampler, Gate};
+use growth::{ChromosomeGrowth, GrowthRule};
+use model::{CoDi, NeuronModel};
+use super::{Cajal, CajalError, PAGE_WIDTH};
+
+// Everything `Page::new` draws at random for a cell (chromosome, gate and threshold), for
+// every cell of a grid, kept page by page in the order pages store their cells.  Bodies
+// are placed from a rng of their own, seeded from the config a genome is built with.
+// Pages are shared with copies of the genome and the grids built from it until changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Genome {
+    pages_x: u32,
+    pages_y: u32,
+    pages: Vec<Arc<Vec<Cell>>>,
+}
+
+impl Genome {
+    // The genome a grid built from `config` starts out with
+    pub fn from_config(config: &CajalConfig) -> Result<Genome, CajalError> {
+        try!(::check_config::<Gate>(config));
+
+        let mut pages = Vec::with_capacity((config.pages_x() * config.pages_y()) as usize);
+        for page_y in 0..config.pages_y() {
+            for page_x in 0..config.pages_x() {
+                let genes = grid::page_genes(config, page_x * PAGE_WIDTH, page_y * PAGE_WIDTH);
+                pages.push(Arc::new(genes));
+            }
+        }
+
+        Ok(Genome {
+            pages_x: config.pages_x(),
+            pages_y: config.pages_y(),
+            pages: pages,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.pages_x * PAGE_WIDTH
+    }
+
+    pub fn height(&self) -> u32 {
+        self.pages_y * PAGE_WIDTH
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> &Cell {
+        let (page, index) = self.locate(x, y);
+        &self.pages[page][index]
+    }
+
+    pub fn get_mut(&mut self, x: u32, y: u32) -> &mut Cell {
+        let (page, index) = self.locate(x, y);
+        &mut Arc::make_mut(&mut self.pages[page])[index]
+    }
+
+    // The genes of the page at the given offset, in the order the page stores its cells
+    pub fn page(&self, offset_x: u32, offset_y: u32) -> &[Cell] {
+        let (page, _) = self.locate(offset_x, offset_y);
+        &self.pages[page][..]
+    }
+
+    // Same, for a grid to hold on to without copying them
+    pub fn shared_page(&self, offset_x: u32, offset_y: u32) -> Arc<Vec<Cell>> {
+        let (page, _) = self.locate(offset_x, offset_y);
+        self.pages[page].clone()
+    }
+
+    fn locate(&self, x: u32, y: u32) -> (usize, usize) {
+        let page = y / PAGE_WIDTH * self.pages_x + x / PAGE_WIDTH;
+        (page as usize, zorder::xy_to_z(x % PAGE_WIDTH, y % PAGE_WIDTH) as usize)
+    }
+
+    // Redraws the genes of each cell with probability `rate`, from the distributions of
+    // `config`.  Returns how many cells changed.  The gaps between mutated cells are
+    // geometric, so only mutated cells cost a draw
+    pub fn mutate<R: Rng>(&mut self, config: &CajalConfig, rate: f32, rng: &mut R) -> u32 {
+        if rate <= 0.0 {
+            return 0;
+        }
+
+        let sampler: GeneSampler = GeneSampler::new(config);
+        let page_size = (PAGE_WIDTH * PAGE_WIDTH) as u64;
+        let cells = self.pages.len() as u64 * page_size;
+        let mut mutations = 0;
+        let mut next = 0;
+        loop {
+            if rate < 1.0 {
+                let gap = (1.0 - rng.next_f64()).ln() / (1.0 - rate as f64).ln();
+                if gap >= (cells - next) as f64 {
+                    break;
+                }
+                next += gap as u64;
+            }
+            if next >= cells {
+                break;
+            }
+
+            let page = Arc::make_mut(&mut self.pages[(next / page_size) as usize]);
+            page[(next % page_size) as usize] = sampler.sample(rng);
+            mutations += 1;
+            next += 1;
+        }
+        mutations
+    }
+
+    // A child that takes a random rectangle from `other` and everything else from `self`,
+    // so that neighbouring genes tend to stay together
+    pub fn crossover<R: Rng>(&self, other: &Genome, rng: &mut R) -> Genome {
+        assert!(self.pages_x == other.pages_x && self.pages_y == other.pages_y);
+
+        let (x1, x2) = span(self.width(), rng);
+        let (y1, y2) = span(self.height(), rng);
+
+        let mut child = self.clone();
+        for y in y1..y2 {
+            for x in x1..x2 {
+                *child.get_mut(x, y) = *other.get(x, y);
+            }
+        }
+        child
+    }
+}
+
+// A random non-empty range within [0, len)
+fn span<R: Rng>(len: u32, rng: &mut R) -> (u32, u32) {
+    let range = Range::new(0, len + 1);
+    let (a, b) = (range.ind_sample(rng), range.ind_sample(rng));
+    match a.cmp(&b) {
+        Ordering::Less => (a, b),
+        Ordering::Greater => (b, a),
+        Ordering::Equal => if a == len { (a - 1, a) } else { (a, a + 1) },
+    }
+}
+
+
+// Evolves a population of genomes against a fitness function, higher being better.  Every
+// genome is built and fully grown into a `Cajal` before the fitness function gets to it
+pub struct Evolution<F, M = CoDi, G = ChromosomeGrowth> {
+    config: CajalConfig,
+    fitness: F,
+    model: M,
+    growth: G,
+    population: Vec<Genome>,
+    rng: StdRng,
+    generation: u32,
+    mutation_rate: f32,
+    crossover_rate: f32,
+    elitism: usize,
+    tournament: usize,
+}
+
+impl<F> Evolution<F>
+    where F: Fn(&mut Cajal) -> f32 + Sync
+{
+    pub fn new(config: &CajalConfig, size: usize, fitness: F) -> Result<Evolution<F>, CajalError> {
+        Evolution::with_rules(config, size, CoDi, ChromosomeGrowth, fitness)
+    }
+}
+
+impl<F, M, G> Evolution<F, M, G>
+    where F: Fn(&mut Cajal<M, G>) -> f32 + Sync,
+          M: NeuronModel + Clone,
+          G: GrowthRule + Clone
+{
+    // The initial population is drawn like grids with the seed of `config` extended by
+    // the index of each genome.  Every genome grows with its own copy of `model` and
+    // `growth`
+    pub fn with_rules(config: &CajalConfig,
+                      size: usize,
+                      model: M,
+                      growth: G,
+                      fitness: F)
+                      -> Result<Evolution<F, M, G>, CajalError> {
+        if size == 0 {
+            return Err(CajalError::Config(ConfigError::Invalid("a population needs at least \
+                                                                one genome"
+                                                                   .to_string())));
+        }
+
+        let mut population = Vec::with_capacity(size);
+        for i in 0..size {
+            let mut seed = config.seed.clone();
+            seed.push(i);
+            population.push(try!(Genome::from_config(&CajalConfig {
+                seed: seed,
+                ..config.clone()
+            })));
+        }
+
+        let mut seed = config.seed.clone();
+        seed.push(size);
+        Ok(Evolution {
+            config: config.clone(),
+            fitness: fitness,
+            model: model,
+            growth: growth,
+            population: population,
+            rng: SeedableRng::from_seed(seed.as_slice()),
+            generation: 0,
+            mutation_rate: 0.001,
+            crossover_rate: 0.5,
+            elitism: 1,
+            tournament: 3,
+        })
+    }
+
+    // Probability of each cell being redrawn in a child
+    pub fn mutation_rate(mut self, rate: f32) -> Evolution<F, M, G> {
+        self.mutation_rate = rate;
+        self
+    }
+
+    // Probability of a child having two parents rather than one
+    pub fn crossover_rate(mut self, rate: f32) -> Evolution<F, M, G> {
+        self.crossover_rate = rate;
+        self
+    }
+
+    // How many of the fittest genomes survive into the next generation unchanged
+    pub fn elitism(mut self, elitism: usize) -> Evolution<F, M, G> {
+        self.elitism = elitism;
+        self
+    }
+
+    // Parents are the fittest of this many genomes picked at random
+    pub fn tournament(mut self, size: usize) -> Evolution<F, M, G> {
+        self.tournament = size;
+        self
+    }
+
+    pub fn population(&self) -> &[Genome] {
+        &self.population
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // Scores every genome of the population, in parallel
+    pub fn evaluate(&self) -> Result<Vec<f32>, CajalError> {
+        let (config, fitness) = (&self.config, &self.fitness);
+        let (model, growth) = (&self.model, &self.growth);
+        let mut results: Vec<Result<f32, CajalError>> = Vec::with_capacity(self.population.len());
+        self.population
+            .par_iter()
+            .map(|genome| {
+                let mut cajal = try!(Cajal::try_from_genome_with_rules(config,
+                                                                       genome,
+                                                                       model.clone(),
+                                                                       growth.clone()));
+                cajal.grow();
+                Ok(fitness(&mut cajal))
+            })
+            .collect_into(&mut results);
+
+        let mut scores = Vec::with_capacity(results.len());
+        for result in results {
+            scores.push(try!(result));
+        }
+        Ok(scores)
+    }
+
+    // Replaces the population with the next generation.  Returns the fittest genome of
+    // the generation that was replaced, along with its fitness
+    pub fn step(&mut self) -> Result<(Genome, f32), CajalError> {
+        let scores = try!(self.evaluate());
+        let mut ranked: Vec<usize> = (0..self.population.len()).collect();
+        ranked.sort_by(|a, b| scores[*b].partial_cmp(&scores[*a]).unwrap_or(Ordering::Equal));
+
+        let mut next = Vec::with_capacity(self.population.len());
+        for i in ranked.iter().take(self.elitism) {
+            next.push(self.population[*i].clone());
+        }
+        while next.len() < self.population.len() {
+            let mut child = {
+                let a = self.select(&scores);
+                if self.rng.next_f32() < self.crossover_rate {
+                    let b = self.select(&scores);
+                    self.population[a].crossover(&self.population[b], &mut self.rng)
+                } else {
+                    self.population[a].clone()
+                }
+            };
+            child.mutate(&self.config, self.mutation_rate, &mut self.rng);
+            next.push(child);
+        }
+
+        let best = (self.population[ranked[0]].clone(), scores[ranked[0]]);
+        debug!("Generation {}: best fitness {}", self.generation, best.1);
+        self.population = next;
+        self.generation += 1;
+        Ok(best)
+    }
+
+    // Runs `generations` steps, returning the fittest genome seen along the way
+    pub fn run(&mut self, generations: u32) -> Result<(Genome, f32), CajalError> {
+        let mut best = try!(self.step());
+        for _ in 1..generations {
+            let candidate = try!(self.step());
+            if candidate.1 > best.1 {
+                best = candidate;
+            }
+        }
+        Ok(best)
+    }
+
+    // Tournament selection, returns an index into the population
+    fn select(&mut self, scores: &[f32]) -> usize {
+        let range = Range::new(0, self.population.len());
+        let mut winner = range.ind_sample(&mut self.rng);
+        for _ in 1..self.tournament {
+            let candidate = range.ind_sample(&mut self.rng);
+            if scores[candidate] > scores[winner] {
+                winner = candidate;
+            }
+        }
+        winner
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use rand::{SeedableRng, StdRng};
+    use super::{Evolution, Genome};
+    use grid;
+    use {Cajal, CajalConfig, CellType};
+
+    fn config() -> CajalConfig {
+        CajalConfig { size: 1, density: 0.01, ..CajalConfig::default() }
+    }
+
+    #[test]
+    fn genome_matches_seed() {
+        let config = config();
+        let genome = Genome::from_config(&config).unwrap();
+        assert!(genome.page(0, 0) == grid::page_genes(&config, 0, 0).as_slice());
+
+        let cajal = Cajal::try_from_genome(&config, &genome).unwrap();
+        let again = Cajal::try_from_genome(&config, &genome).unwrap();
+        assert!(cajal.state_hash() == again.state_hash());
+        let mut bodies = 0;
+        for y in 0..cajal.height() {
+            for x in 0..cajal.width() {
+                let cell = cajal.get_cell(x, y);
+                if cell.get_cell_type() == CellType::Body {
+                    bodies += 1;
+                } else if cell.get_cell_type() == CellType::Empty {
+                    assert!(cell.raw() == genome.get(x, y).raw());
+                }
+            }
+        }
+        assert!(bodies > 600 && bodies <= 655);
+
+        let other = CajalConfig { size: 2, ..config };
+        assert!(Cajal::try_from_genome(&other, &genome).is_err());
+    }
+
+    #[test]
+    fn operators() {
+        let config = config();
+        let mut rng: StdRng = SeedableRng::from_seed(&[1usize, 2, 3][..]);
+        let a = Genome::from_config(&config).unwrap();
+        let b = Genome::from_config(&CajalConfig { seed: vec![5, 6], ..config.clone() }).unwrap();
+
+        let child = a.crossover(&b, &mut rng);
+        let mut from_b = 0;
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                let gene = child.get(x, y).raw();
+                assert!(gene == a.get(x, y).raw() || gene == b.get(x, y).raw());
+                if gene != a.get(x, y).raw() {
+                    from_b += 1;
+                }
+            }
+        }
+        assert!(from_b > 0);
+
+        let mut mutant = a.clone();
+        assert!(mutant.mutate(&config, 0.0, &mut rng) == 0);
+        assert!(mutant == a);
+        assert!(mutant.mutate(&config, 1.0, &mut rng) == 256 * 256);
+
+        // About one cell in a hundred, and never more than the ones that differ
+        let mut mutant = a.clone();
+        let mutations = mutant.mutate(&config, 0.01, &mut rng);
+        assert!(mutations > 500 && mutations < 800);
+        let mut changed = 0;
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                if mutant.get(x, y).raw() != a.get(x, y).raw() {
+                    changed += 1;
+                }
+            }
+        }
+        assert!(changed > 0 && changed <= mutations);
+    }
+
+    #[test]
+    fn evolution_keeps_the_best() {
+        let axons = |cajal: &mut Cajal| {
+            let mut count = 0;
+            for x in 0..cajal.width() {
+                for y in 0..cajal.height() {
+                    if cajal.get_cell(x, y).get_cell_type() == CellType::Axon {
+                        count += 1;
+                    }
+                }
+            }
+            count as f32
+        };
+
+        let mut evolution = Evolution::new(&config(), 4, axons).unwrap().mutation_rate(0.01);
+        let (_, first) = evolution.step().unwrap();
+        let (_, second) = evolution.step().unwrap();
+        assert!(second >= first);
+        assert!(evolution.generation() == 2);
+        assert!(evolution.population().len() == 4);
+
+        assert!(Evolution::new(&config(), 0, axons).is_err());
+    }
+}
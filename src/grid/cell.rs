@@ -6,29 +6,34 @@ use rand::Rng;
 use rand::Rand;
 use rand::distributions::{IndependentSample, Range};
 
-const CELL_TYPE_MASK: u32 = 0b0000000000_00_00_00_000000_0000_0_00_111;  // ---
-const GATE_MASK: u32      = 0b0000000000_00_00_00_000000_0000_0_11_000;  // | Growth Phase
-const STIM_MASK: u32      = 0b0000000000_00_00_00_000000_0000_1_00_000;  // |
-const CHROMO_MASK: u32    = 0b0000000000_00_00_00_000000_1111_0_00_000;  // ---
-
-const STRENGTH_MASK: u32  = 0b0000_000000_00_00_00_000000_1111_0_00_000;  // ---
-const THRESHOLD_MASK: u32 = 0b0000_000000_00_00_00_111111_0000_0_00_000;  // | Signal Phase
-const POT_1_MASK: u32     = 0b0000_000000_00_00_11_000000_0000_0_00_000;  // |
-const POT_2_MASK: u32     = 0b0000_000000_00_11_00_000000_0000_0_00_000;  // |
-const POT_3_MASK: u32     = 0b0000_000000_11_00_00_000000_0000_0_00_000;  // |
-const SIGNAL_MASK: u32    = 0b0000_111111_00_00_00_000000_0000_0_00_000;  // ---
+// Gates and chromosomes are wide enough for the six directions of a 3D grid, flat grids
+// leave their top bits unused.  Nothing grows once signal flows, so the potentiation
+// counters take over the chromosome bits
+const CELL_TYPE_MASK: u32 = 0b000_0000_000000_000000_000000_0_000_111;  // ---
+const GATE_MASK: u32      = 0b000_0000_000000_000000_000000_0_111_000;  // | Growth Phase
+const STIM_MASK: u32      = 0b000_0000_000000_000000_000000_1_000_000;  // |
+const CHROMO_MASK: u32    = 0b000_0000_000000_000000_111111_0_000_000;  // ---
+
+const THRESHOLD_MASK: u32 = 0b000_0000_000000_111111_000000_0_000_000;  // ---
+const SIGNAL_MASK: u32    = 0b000_0000_111111_000000_000000_0_000_000;  // | Signal Phase
+const POT_1_MASK: u32     = 0b000_0000_000000_000000_000011_0_000_000;  // |
+const POT_2_MASK: u32     = 0b000_0000_000000_000000_001100_0_000_000;  // |
+const POT_3_MASK: u32     = 0b000_0000_000000_000000_110000_0_000_000;  // ---
+
+const STRENGTH_MASK: u32  = 0b000_1111_000000_000000_000000_0_000_000;  // Both phases
 
 const CELL_TYPE_OFFSET: u8 = 0;
 const GATE_OFFSET: u8 = 3;
-const STIM_OFFSET: u8 = 5;
-const CHROMO_OFFSET: u8 = 6;
+const STIM_OFFSET: u8 = 6;
+const CHROMO_OFFSET: u8 = 7;
 
-const STRENGTH_OFFSET: u8 = 6;
-const THRESHOLD_OFFSET: u8 = 10;
-const POT_1_OFFSET: u8 = 16;
-const POT_2_OFFSET: u8 = 18;
-const POT_3_OFFSET: u8 = 20;
-const SIGNAL_OFFSET: u8 = 22;
+const THRESHOLD_OFFSET: u8 = 13;
+const SIGNAL_OFFSET: u8 = 19;
+const POT_1_OFFSET: u8 = 7;
+const POT_2_OFFSET: u8 = 9;
+const POT_3_OFFSET: u8 = 11;
+
+const STRENGTH_OFFSET: u8 = 25;
 
 enum_from_primitive! {
     #[derive(Debug, PartialEq, Copy, Clone)]
@@ -252,16 +257,141 @@ impl From<Gate> for Chromosome {
 }
 
 
-#[derive(Clone, Copy, Debug)]
+enum_from_primitive! {
+    #[derive(Debug, PartialEq, Copy, Clone)]
+    pub enum Gate3D {
+        North = 0b000,
+        West = 0b001,
+        South = 0b010,
+        East = 0b011,
+        Up = 0b100,
+        Down = 0b101
+    }
+}
+
+impl Not for Gate3D {
+    type Output = Gate3D;
+
+    fn not(self) -> Gate3D {
+        match self {
+            Gate3D::North => Gate3D::South,
+            Gate3D::South => Gate3D::North,
+            Gate3D::East => Gate3D::West,
+            Gate3D::West => Gate3D::East,
+            Gate3D::Up => Gate3D::Down,
+            Gate3D::Down => Gate3D::Up,
+        }
+    }
+}
+
+impl Rand for Gate3D {
+    fn rand<R: Rng>(rng: &mut R) -> Gate3D {
+        let range = Range::new(0, 6);
+        match Gate3D::from_u32(range.ind_sample(rng)) {
+            Some(g) => g,
+            None => unreachable!(),
+        }
+    }
+}
+
+// With six directions there are 64 combinations, so rather than spelling each one
+// out like the 2D `Chromosome`, this is a plain set of direction flags
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Chromosome3D {
+    bits: u8,
+}
+
+impl Chromosome3D {
+    pub fn block() -> Chromosome3D {
+        Chromosome3D { bits: 0 }
+    }
+
+    pub fn all() -> Chromosome3D {
+        Chromosome3D { bits: 0b111111 }
+    }
+
+    pub fn from_bits(bits: u8) -> Chromosome3D {
+        Chromosome3D { bits: bits & 0b111111 }
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    pub fn with(&self, direction: Gate3D) -> Chromosome3D {
+        Chromosome3D { bits: self.bits | Chromosome3D::flag(direction) }
+    }
+
+    pub fn contains(&self, direction: Gate3D) -> bool {
+        self.bits & Chromosome3D::flag(direction) != 0
+    }
+
+    pub fn is_block(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn invert(&self) -> Chromosome3D {
+        Chromosome3D { bits: !self.bits & 0b111111 }
+    }
+
+    fn flag(direction: Gate3D) -> u8 {
+        1 << (direction as u8)
+    }
+}
+
+impl From<Gate3D> for Chromosome3D {
+    fn from(gate: Gate3D) -> Chromosome3D {
+        Chromosome3D::block().with(gate)
+    }
+}
+
+impl fmt::Binary for Chromosome3D {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#b}", self.bits)
+    }
+}
+
+impl Rand for Chromosome3D {
+    fn rand<R: Rng>(rng: &mut R) -> Chromosome3D {
+        let range = Range::new(0, 64);
+        Chromosome3D::from_bits(range.ind_sample(rng))
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Cell {
     data: u32,
 }
 
+// Stands in for every cell of a page that hasn't been allocated yet
+pub static EMPTY: Cell = Cell { data: 0 };
+
 impl Cell {
     pub fn new() -> Cell {
         Cell { data: 0 }
     }
 
+    pub fn from_raw(data: u32) -> Cell {
+        Cell { data: data }
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.data
+    }
+
+    // Whether raw data from outside, such as a snapshot, holds a cell type and a gate of a
+    // flat grid.  The chromosome bits may hold potentiation, so any value goes there
+    pub fn is_valid(&self) -> bool {
+        self.is_valid_3d() && Gate::from_u32((self.data & GATE_MASK) >> GATE_OFFSET).is_some()
+    }
+
+    // Same for a 3D grid
+    pub fn is_valid_3d(&self) -> bool {
+        CellType::from_u32((self.data & CELL_TYPE_MASK) >> CELL_TYPE_OFFSET).is_some() &&
+        Gate3D::from_u32((self.data & GATE_MASK) >> GATE_OFFSET).is_some()
+    }
+
     pub fn set_cell_type(&mut self, cell_type: CellType) {
         self.data = (self.data & !CELL_TYPE_MASK) | ((cell_type as u32) << CELL_TYPE_OFFSET);
     }
@@ -288,17 +418,44 @@ impl Cell {
         self.data = (self.data & !CHROMO_MASK) | ((chromo as u32) << CHROMO_OFFSET);
     }
 
+    // Flat grids only use the low four bits
     pub fn get_chromosome(&self) -> Chromosome {
-        match Chromosome::from_u32((self.data & CHROMO_MASK) >> CHROMO_OFFSET) {
+        match Chromosome::from_u32(((self.data & CHROMO_MASK) >> CHROMO_OFFSET) & 0b1111) {
             Some(c) => c,
             None => unreachable!(),
         }
     }
 
+    pub fn set_gate_3d(&mut self, gate: Gate3D) {
+        self.data = (self.data & !GATE_MASK) | ((gate as u32) << GATE_OFFSET);
+    }
+
+    pub fn get_gate_3d(&self) -> Gate3D {
+        match Gate3D::from_u32((self.data & GATE_MASK) >> GATE_OFFSET) {
+            Some(g) => g,
+            None => unreachable!(),
+        }
+    }
+
+    pub fn set_chromosome_3d(&mut self, chromo: Chromosome3D) {
+        self.data = (self.data & !CHROMO_MASK) | ((chromo.bits() as u32) << CHROMO_OFFSET);
+    }
+
+    pub fn get_chromosome_3d(&self) -> Chromosome3D {
+        Chromosome3D::from_bits(((self.data & CHROMO_MASK) >> CHROMO_OFFSET) as u8)
+    }
+
     pub fn get_strength(&self) -> u8 {
         ((self.data & STRENGTH_MASK) >> STRENGTH_OFFSET) as u8
     }
 
+    pub fn set_strength(&mut self, strength: u8) {
+        self.data = match strength {
+            0...15 => (self.data & !STRENGTH_MASK) | ((strength as u32) << STRENGTH_OFFSET),
+            _ => (self.data & !STRENGTH_MASK) | (15 << STRENGTH_OFFSET),
+        };
+    }
+
     pub fn set_threshold(&mut self, threshold: u8) {
         self.data = match threshold {
             0...63 => (self.data & !THRESHOLD_MASK) | ((threshold as u32) << THRESHOLD_OFFSET),
@@ -357,11 +514,48 @@ impl Cell {
     pub fn set_stim(&mut self, stim: bool) {
         self.data = (self.data & !STIM_MASK) | ((stim as u32) << STIM_OFFSET);
     }
+
+    // Potentiation level of the contacts of an axon, scales the strength of the signal it
+    // sends into dendrites and bodies
+    pub fn get_pot_level(&self) -> u8 {
+        ((self.data & POT_1_MASK) >> POT_1_OFFSET) as u8
+    }
+
+    pub fn set_pot_level(&mut self, level: u8) {
+        self.data = match level {
+            0...3 => (self.data & !POT_1_MASK) | ((level as u32) << POT_1_OFFSET),
+            _ => (self.data & !POT_1_MASK) | (3 << POT_1_OFFSET),
+        };
+    }
+
+    // Co-active deliveries seen since the level last changed
+    pub fn get_ltp_count(&self) -> u8 {
+        ((self.data & POT_2_MASK) >> POT_2_OFFSET) as u8
+    }
+
+    pub fn set_ltp_count(&mut self, count: u8) {
+        self.data = match count {
+            0...3 => (self.data & !POT_2_MASK) | ((count as u32) << POT_2_OFFSET),
+            _ => (self.data & !POT_2_MASK) | (3 << POT_2_OFFSET),
+        };
+    }
+
+    // Deliveries to a silent cell seen since the level last changed
+    pub fn get_ltd_count(&self) -> u8 {
+        ((self.data & POT_3_MASK) >> POT_3_OFFSET) as u8
+    }
+
+    pub fn set_ltd_count(&mut self, count: u8) {
+        self.data = match count {
+            0...3 => (self.data & !POT_3_MASK) | ((count as u32) << POT_3_OFFSET),
+            _ => (self.data & !POT_3_MASK) | (3 << POT_3_OFFSET),
+        };
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Cell, Gate, Chromosome};
+    use super::{Cell, CellType, Gate, Gate3D, Chromosome, Chromosome3D};
 
     #[test]
     fn toggle_gates() {
@@ -440,6 +634,26 @@ mod test {
         assert!(c.get_chromosome().contains(Chromosome::NorthSouth));
     }
 
+    #[test]
+    fn toggle_potentiation() {
+        let mut c = Cell::new();
+        c.set_signal(63);
+        c.set_threshold(63);
+
+        c.set_pot_level(3);
+        c.set_ltp_count(2);
+        c.set_ltd_count(1);
+        assert!(c.get_pot_level() == 3 && c.get_ltp_count() == 2 && c.get_ltd_count() == 1);
+        assert!(c.get_signal() == 63 && c.get_threshold() == 63);
+        assert!(c.is_valid());
+
+        // overflow
+        c.set_pot_level(5);
+        c.set_ltp_count(4);
+        c.set_ltd_count(255);
+        assert!(c.get_pot_level() == 3 && c.get_ltp_count() == 3 && c.get_ltd_count() == 3);
+    }
+
     #[test]
     fn set_threshold() {
         let mut c = Cell::new();
@@ -453,6 +667,51 @@ mod test {
         assert!(c.get_threshold() == 63u8);
     }
 
+    #[test]
+    fn set_strength() {
+        let mut c = Cell::new();
+        c.set_chromosome(Chromosome::All);
+        c.set_signal(63);
+
+        c.set_strength(9);
+        assert!(c.get_strength() == 9u8);
+        assert!(c.get_chromosome() == Chromosome::All && c.get_signal() == 63);
+
+        // overflow
+        c.set_strength(40);
+        assert!(c.get_strength() == 15u8);
+    }
+
+    #[test]
+    fn toggle_3d() {
+        let mut c = Cell::new();
+        assert!(c.get_gate_3d() == Gate3D::North);
+        assert!(c.get_chromosome_3d().is_block());
+
+        c.set_gate_3d(Gate3D::Down);
+        c.set_chromosome_3d(Chromosome3D::from(Gate3D::Up).with(Gate3D::West));
+        c.set_cell_type(CellType::Axon);
+        c.set_strength(15);
+        c.set_threshold(12);
+
+        assert!(c.get_gate_3d() == Gate3D::Down);
+        assert!(c.get_chromosome_3d().contains(Gate3D::Up));
+        assert!(c.get_chromosome_3d().contains(Gate3D::West));
+        assert!(!c.get_chromosome_3d().contains(Gate3D::Down));
+        assert!(c.get_cell_type() == CellType::Axon);
+        assert!(c.get_strength() == 15 && c.get_threshold() == 12);
+        assert!(c.is_valid_3d() && !c.is_valid());
+    }
+
+    #[test]
+    fn chromosome_3d_invert() {
+        let c = Chromosome3D::from(Gate3D::Up).invert();
+        assert!(!c.contains(Gate3D::Up));
+        assert!(c.contains(Gate3D::Down));
+        assert!(c.contains(Gate3D::North));
+        assert!(Chromosome3D::all().invert() == Chromosome3D::block());
+    }
+
     #[test]
     fn add_signal() {
         let mut c = Cell::new();
@@ -0,0 +1,145 @@
+use rayon::par_iter::*;
+
+use memory;
+
+// Messages that cross page edges, bucketed by the page they are bound for.  Buckets move
+// from the outbox of the sending page to the inbox of the receiving one and back again,
+// so once they have grown to size routing doesn't allocate anymore
+#[derive(Clone)]
+pub struct Mail<T> {
+    // What each page sent, swapped for the queue the page fills while it works
+    sent: Vec<Vec<T>>,
+    // For every sending page, a (receiving page, messages) bucket per page it sent to
+    outboxes: Vec<Vec<(usize, Vec<T>)>>,
+    // For every receiving page, (sending page, messages) in sending page order
+    inboxes: Vec<Vec<(usize, Vec<T>)>>,
+}
+
+impl<T: Send> Mail<T> {
+    pub fn new(pages: usize) -> Mail<T> {
+        Mail {
+            sent: (0..pages).map(|_| Vec::new()).collect(),
+            outboxes: (0..pages).map(|_| Vec::new()).collect(),
+            inboxes: (0..pages).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    // The queue a page hands over, to be filled with what it sends.  Empty, since
+    // everything sent last time has been routed
+    pub fn sent(&mut self, page: usize) -> &mut Vec<T> {
+        &mut self.sent[page]
+    }
+
+    // Works out where everything sent lands, in parallel for every sending page, and
+    // files it into the inboxes.  Every inbox lists its buckets in page order, so receiving
+    // them has the same effect as handing messages over one at a time.  Returns how many
+    // messages were sent, including those that didn't land anywhere
+    pub fn route<F>(&mut self, resolve: F) -> u32
+        where F: Fn(T) -> Option<(usize, T)> + Sync
+    {
+        let sent = self.sent.iter().map(|sent| sent.len() as u32).fold(0u32, |acc, x| acc + x);
+        {
+            let resolve = &resolve;
+            self.sent
+                .par_iter_mut()
+                .zip(&mut self.outboxes)
+                .weight_max()
+                .for_each(|(sent, outbox)| {
+                    for message in sent.drain(..) {
+                        if let Some((page, message)) = resolve(message) {
+                            match outbox.iter().position(|&(p, _)| p == page) {
+                                Some(i) => outbox[i].1.push(message),
+                                None => outbox.push((page, vec![message])),
+                            }
+                        }
+                    }
+                });
+        }
+
+        for (sender, outbox) in self.outboxes.iter_mut().enumerate() {
+            for (page, messages) in outbox.drain(..) {
+                self.inboxes[page].push((sender, messages));
+            }
+        }
+        sent
+    }
+
+    // Buckets a page emptied keep their place until `recycle`, along with buckets that
+    // came back empty from earlier steps
+    pub fn inboxes(&mut self) -> &mut Vec<Vec<(usize, Vec<T>)>> {
+        &mut self.inboxes
+    }
+
+    // Returns every bucket to the page that sent it, once the inboxes have been emptied
+    pub fn recycle(&mut self) {
+        for (page, inbox) in self.inboxes.iter_mut().enumerate() {
+            for (sender, mut messages) in inbox.drain(..) {
+                messages.clear();
+                self.outboxes[sender].push((page, messages));
+            }
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let mut bytes = memory::vec_bytes(&self.sent) + memory::vec_bytes(&self.outboxes) +
+                        memory::vec_bytes(&self.inboxes);
+        for sent in &self.sent {
+            bytes += memory::vec_bytes(sent);
+        }
+        for buckets in self.outboxes.iter().chain(&self.inboxes) {
+            bytes += memory::vec_bytes(buckets);
+            for &(_, ref messages) in buckets {
+                bytes += memory::vec_bytes(messages);
+            }
+        }
+        bytes
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::Mail;
+
+    fn send(mail: &mut Mail<u32>, outgoing: Vec<Vec<u32>>) {
+        for (page, messages) in outgoing.into_iter().enumerate() {
+            mail.sent(page).extend(messages);
+        }
+    }
+
+    // Odd messages go to page 1, multiples of four get dropped, the rest go to page 3
+    fn resolve(m: u32) -> Option<(usize, u32)> {
+        match m {
+            m if m % 2 == 1 => Some((1, m)),
+            m if m % 4 == 0 => None,
+            m => Some((3, m)),
+        }
+    }
+
+    #[test]
+    fn route_keeps_page_order() {
+        let mut mail = Mail::new(4);
+        send(&mut mail, vec![vec![1, 4, 5], vec![3, 7], vec![], vec![8, 9, 2]]);
+        assert!(mail.route(resolve) == 8);
+        assert!(*mail.inboxes() ==
+                vec![vec![],
+                     vec![(0, vec![1, 5]), (1, vec![3, 7]), (3, vec![9])],
+                     vec![],
+                     vec![(3, vec![2])]]);
+    }
+
+    #[test]
+    fn buckets_are_reused() {
+        let mut mail = Mail::new(4);
+        send(&mut mail, vec![vec![1, 5, 7], vec![], vec![], vec![]]);
+        mail.route(resolve);
+        mail.recycle();
+        assert!(mail.inboxes().iter().all(|inbox| inbox.is_empty()));
+        assert!(mail.outboxes[0].len() == 1 && mail.outboxes[0][0].1.capacity() >= 3);
+
+        send(&mut mail, vec![vec![3], vec![], vec![], vec![]]);
+        assert!(mail.route(resolve) == 1);
+        assert!(mail.inboxes()[1] == vec![(0, vec![3])]);
+        assert!(mail.inboxes()[1][0].1.capacity() >= 3);
+    }
+}
@@ -1,209 +1,868 @@
 
 use rayon::par_iter::*;
-use self::page::Page;
-use super::{ReportMemory, PAGE_SIZE, PAGE_WIDTH};
-
-pub use self::page::{Cell, CellType};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::time::Instant;
+use self::mail::Mail;
+use self::page::{Page, RemoteChange, RemoteContact, RemoteSignal};
+use self::plasticity::Stdp;
+use config::{Boundary, CajalConfig, Plasticity};
+use evolve::Genome;
+use growth::{ChromosomeGrowth, GrowthRule};
+use memory::MemoryReport;
+use model::{CoDi, NeuronModel};
+use report::StepReport;
+use snapshot::{self, Snapshot};
+
+pub use self::cell::{Chromosome3D, Gate3D};
+pub use self::neighbourhood::Neighbourhood;
+pub use self::page::{Cell, CellType, Chromosome, Gate, GeneSampler};
 
 mod cell;
+mod mail;
+mod neighbourhood;
 mod page;
-mod zorder;
+mod plasticity;
+pub mod zorder;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// FNV-1a, fed one byte at a time so the result doesn't depend on endianness
+pub fn hash_u32(hash: u64, value: u32) -> u64 {
+    let mut hash = hash;
+    for i in 0..4 {
+        hash ^= ((value >> (i * 8)) & 0xff) as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
 
 
+// A grid of pages of either kind, `N` being the gate that tells them apart
+#[derive(Clone)]
+pub struct Grid<M = CoDi, G = ChromosomeGrowth, N = Gate> {
+    pages: Vec<Page<N>>,
+    width: u32,
+    height: u32,
+    depth: u32,
+    pages_x: u32,
+    pages_y: u32,
+    pages_z: u32,
+    // Kept around to allocate placeholder pages once growth reaches them
+    config: CajalConfig,
+    // Signal steps taken so far
+    step: u64,
+    // Growth steps taken so far
+    generation: u64,
+    stdp: Stdp,
+    // Growth, signal and Hebbian updates crossing page edges, empty between steps
+    change_mail: Mail<RemoteChange<N>>,
+    signal_mail: Mail<RemoteSignal<N>>,
+    contact_mail: Mail<RemoteContact>,
+    model: M,
+    growth: G,
+}
 
-pub struct Grid {
-    pages: Vec<Page>,
-    dimension: u32,
-    pages_per_side: u32,
+impl Grid {
+    pub fn new(config: &CajalConfig) -> Grid {
+        Grid::with_rules(config, CoDi, ChromosomeGrowth)
+    }
 }
 
-impl ReportMemory for Grid {
-    fn memory(&self) -> u32 {
-        self.pages
-            .into_par_iter()
-            .map(|page| page.memory())
-            .sum()
+impl<M: NeuronModel, G: GrowthRule> Grid<M, G> {
+    // The genome has to match the dimensions of the config
+    pub fn with_genome(config: &CajalConfig, genome: &Genome, model: M, growth: G) -> Grid<M, G> {
+        Grid::build(config,
+                    model,
+                    growth,
+                    |x, y, z| Page::from_genes(config, x, y, z, genome.shared_page(x, y)))
+    }
+
+    pub fn get_cell(&self, x: u32, y: u32) -> &Cell {
+        self.cell_at(x, y, 0)
+    }
+
+    pub fn get_mut_cell(&mut self, x: u32, y: u32) -> &mut Cell {
+        self.cell_at_mut(x, y, 0)
+    }
+
+    // Bodies that crossed threshold during the last signal step
+    pub fn get_fired(&self) -> Vec<(u32, u32)> {
+        self.fired_cells().into_iter().map(|(x, y, _)| (x, y)).collect()
+    }
+
+    // Whether the cell passed signal on during the last signal step
+    pub fn is_firing(&self, x: u32, y: u32) -> bool {
+        self.is_firing_at(x, y, 0)
+    }
+
+    pub fn set_input(&mut self, x: u32, y: u32, sig: u8) {
+        self.set_input_at(x, y, 0, sig);
+    }
+
+    // The cell adjacent to (x, y) in the given direction, taking the boundary into account
+    pub fn neighbour(&self, x: u32, y: u32, direction: Gate) -> Option<(u32, u32)> {
+        self.neighbour_at(x, y, 0, direction).map(|(x, y, _)| (x, y))
     }
 }
 
-impl Grid {
-    pub fn new(size: u32, density: f32, seed: &[usize]) -> Grid {
-        // todo assert size
-        let num_pages = size * size;
+impl<M: NeuronModel<N>, G: GrowthRule<N>, N: Neighbourhood> Grid<M, G, N> {
+    pub fn with_rules(config: &CajalConfig, model: M, growth: G) -> Grid<M, G, N> {
+        Grid::build(config, model, growth, |x, y, z| Page::new(config, x, y, z))
+    }
+
+    fn build<F>(config: &CajalConfig, model: M, growth: G, new_page: F) -> Grid<M, G, N>
+        where F: Fn(u32, u32, u32) -> Page<N>
+    {
+        let (pages_x, pages_y, pages_z) = (config.pages_x(), config.pages_y(), config.pages_z());
+        let num_pages = pages_x * pages_y * pages_z;
 
-        info!("Creating grid with {} pages per side ({} pages total), each with {} cells ({} \
+        info!("Creating grid with {}x{}x{} pages ({} pages total), each with {} cells ({} \
                total cells)",
-              size,
+              pages_x,
+              pages_y,
+              pages_z,
               num_pages,
-              PAGE_SIZE,
-              num_pages * PAGE_SIZE);
+              N::page_size(),
+              num_pages as u64 * N::page_size() as u64);
 
         let mut pages = Vec::with_capacity(num_pages as usize);
         for i in 0..num_pages {
-            let offset_x = (i as u32 % size) * PAGE_WIDTH;
-            let offset_y = (i as u32 / size) * PAGE_WIDTH;
-            debug!("Offsets: ({},{})", offset_x, offset_y);
-            pages.push(Page::new(density, offset_x, offset_y, seed));
+            let (offset_x, offset_y, offset_z) = Grid::<M, G, N>::page_offset(i, pages_x, pages_y);
+            debug!("Offsets: ({},{},{})", offset_x, offset_y, offset_z);
+            pages.push(new_page(offset_x, offset_y, offset_z));
         }
 
+        let count = pages.len();
         Grid {
             pages: pages,
-            dimension: size * PAGE_WIDTH,
-            pages_per_side: size,
+            width: pages_x * N::page_width(),
+            height: pages_y * N::page_width(),
+            depth: pages_z * N::page_depth(),
+            pages_x: pages_x,
+            pages_y: pages_y,
+            pages_z: pages_z,
+            config: config.clone(),
+            step: 0,
+            generation: 0,
+            stdp: Stdp::default(),
+            change_mail: Mail::new(count),
+            signal_mail: Mail::new(count),
+            contact_mail: Mail::new(count),
+            model: model,
+            growth: growth,
+        }
+    }
+
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(snapshot::write_u32(writer, self.width));
+        try!(snapshot::write_u32(writer, self.height));
+        try!(snapshot::write_u32(writer, self.depth));
+        try!(snapshot::write_u32(writer, self.pages_x));
+        try!(snapshot::write_u32(writer, self.pages_y));
+        try!(snapshot::write_u32(writer, self.pages_z));
+        try!(snapshot::write_u32(writer, self.pages.len() as u32));
+        try!(self.config.save(writer));
+        try!(snapshot::write_u64(writer, self.step));
+        try!(snapshot::write_u64(writer, self.generation));
+        try!(self.stdp.save(writer));
+        for page in &self.pages {
+            try!(page.save(writer));
+        }
+        Ok(())
+    }
+
+    // The model and growth rule are configured in code rather than stored, so they have
+    // to be handed back in
+    pub fn load<R: Read>(reader: &mut R, model: M, growth: G) -> io::Result<Grid<M, G, N>> {
+        let width = try!(snapshot::read_u32(reader));
+        let height = try!(snapshot::read_u32(reader));
+        let depth = try!(snapshot::read_u32(reader));
+        let pages_x = try!(snapshot::read_u32(reader));
+        let pages_y = try!(snapshot::read_u32(reader));
+        let pages_z = try!(snapshot::read_u32(reader));
+        let num_pages = try!(snapshot::read_u32(reader));
+        let config = try!(CajalConfig::load(reader));
+        let step = try!(snapshot::read_u64(reader));
+        let generation = try!(snapshot::read_u64(reader));
+        let stdp = try!(Stdp::load(reader));
+
+        if let Err(e) = config.validate_for::<N>() {
+            return Err(snapshot::invalid_data(&e.to_string()));
+        }
+        if Some(width) != pages_x.checked_mul(N::page_width()) ||
+           Some(height) != pages_y.checked_mul(N::page_width()) ||
+           Some(depth) != pages_z.checked_mul(N::page_depth()) ||
+           Some(num_pages) != pages_x.checked_mul(pages_y).and_then(|n| n.checked_mul(pages_z)) ||
+           pages_x != config.pages_x() || pages_y != config.pages_y() ||
+           pages_z != config.pages_z() {
+            return Err(snapshot::invalid_data("inconsistent grid dimensions"));
         }
+        if !stdp.within(width, height, depth) {
+            return Err(snapshot::invalid_data("spike timing outside of the grid"));
+        }
+
+        let mut pages = Vec::with_capacity(snapshot::capacity(num_pages));
+        for i in 0..num_pages {
+            let page: Page<N> = try!(Page::load(reader));
+            if page.offset() != Grid::<M, G, N>::page_offset(i, pages_x, pages_y) {
+                return Err(snapshot::invalid_data("page out of place"));
+            }
+            pages.push(page);
+        }
+
+        let count = pages.len();
+        Ok(Grid {
+            pages: pages,
+            width: width,
+            height: height,
+            depth: depth,
+            pages_x: pages_x,
+            pages_y: pages_y,
+            pages_z: pages_z,
+            config: config,
+            step: step,
+            generation: generation,
+            stdp: stdp,
+            change_mail: Mail::new(count),
+            signal_mail: Mail::new(count),
+            contact_mail: Mail::new(count),
+            model: model,
+            growth: growth,
+        })
     }
 
     pub fn grow(&mut self) {
         loop {
-            let active_cells = self.grow_step();
+            let report = self.grow_step();
 
-            if active_cells == 0 {
+            if report.active_cells == 0 {
                 break;
             }
         }
     }
 
-    pub fn grow_step(&mut self) -> u32 {
+    pub fn grow_step(&mut self) -> StepReport {
         debug!("Growing Pages...");
+        let mut report = StepReport::default();
+
+        let start = Instant::now();
+        {
+            let (config, growth, generation) = (&self.config, &self.growth, self.generation);
+            self.pages
+                .par_iter_mut()
+                .weight_max()
+                .for_each(|page| page.grow(config, growth, generation));
+        }
+        self.generation += 1;
+        report.process_time = start.elapsed();
+
+        let start = Instant::now();
+        report.local_changes = self.pages
+                                   .iter()
+                                   .map(|page| page.get_change_count())
+                                   .fold(0u32, |acc, x| acc + x);
+
+        // Taken out for the step, so routing can look at the grid while it fills the mail
+        let mut mail = mem::replace(&mut self.change_mail, Mail::new(0));
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            page.take_remote_changes(mail.sent(i));
+        }
+        report.remote_changes = mail.route(|change| self.route_change(change));
+        debug!("Remote changes to process: {}", report.remote_changes);
+        {
+            let config = &self.config;
+            self.pages
+                .par_iter_mut()
+                .zip(mail.inboxes())
+                .weight_max()
+                .for_each(|(page, inbox)| page.receive_changes(config, inbox));
+        }
+        mail.recycle();
+        self.change_mail = mail;
+        report.pages_touched = self.pages.iter().filter(|page| page.is_busy()).count() as u32;
+        report.route_time = start.elapsed();
 
+        debug!("Updating Pages...");
+        let start = Instant::now();
+        let mut grown = Vec::with_capacity(self.pages.len());
         self.pages
             .par_iter_mut()
             .weight_max()
-            .for_each(|page| page.grow());;
+            .map(|page| page.update())
+            .collect_into(&mut grown);
+        for counts in grown {
+            report.bodies_grown += counts[CellType::Body as usize];
+            report.axons_grown += counts[CellType::Axon as usize];
+            report.dendrites_grown += counts[CellType::Dendrite as usize];
+        }
+        report.active_cells = report.grown();
+        report.update_time = start.elapsed();
 
-        let active_cells = self.pages
-                               .iter()
-                               .map(|page| page.get_active_cell_count())
-                               .fold(0u32, |acc, x| acc + x);
+        debug!("Active cells after growth: {}", report.active_cells);
+        report
+    }
 
-        for i in 0..self.pages.len() {
-            let changes = self.pages[i].get_remote_changes().clone();
+    pub fn signal(&mut self) {
+        loop {
+            let report = self.signal_step();
 
-            if changes.is_empty() {
-                continue;
+            if report.active_cells == 0 {
+                break;
             }
+        }
+    }
 
-            debug!("Remote changes to process: {}", changes.len());
+    pub fn signal_step(&mut self) -> StepReport {
+        debug!("Processing signals...");
+        let mut report = StepReport::default();
+
+        let start = Instant::now();
+        {
+            let (config, model) = (&self.config, &self.model);
+            self.pages
+                .par_iter_mut()
+                .weight_max()
+                .for_each(|page| page.signal(config, model));
+        }
+        report.bodies_fired = self.pages
+                                  .iter()
+                                  .map(|page| page.get_fired_count())
+                                  .fold(0u32, |acc, x| acc + x);
+        report.process_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut mail = mem::replace(&mut self.signal_mail, Mail::new(0));
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            page.take_remote_signal(mail.sent(i));
+        }
+        mail.route(|signal| self.route_signal(signal));
+        self.pages
+            .par_iter_mut()
+            .zip(mail.inboxes())
+            .weight_max()
+            .for_each(|(page, inbox)| page.receive_signal(inbox));
+        mail.recycle();
+        self.signal_mail = mail;
 
-            for c in changes {
-                debug!("Absolute change position: ({},{})", c.x, c.y);
-                if !(c.x > 0 && c.x < self.dimension && c.y > 0 && c.y < self.dimension) {
-                    debug!("x > 1 {}", c.x > 0);
-                    debug!("x < dimension - 1{}", c.x < self.dimension);
-                    debug!("y > 1 {}", c.y > 0);
-                    debug!("y < dimension - 1 {}", c.y < self.dimension);
+        report.pages_touched = self.pages.iter().filter(|page| page.is_busy()).count() as u32;
+        report.route_time = start.elapsed();
 
-                    continue;
-                }
-                self.get_mut_page(c.x, c.y)
-                    .add_change(c.x % PAGE_WIDTH,
-                                c.y % PAGE_WIDTH,
-                                c.cell,
-                                c.travel_direction,
-                                c.stim);
+        debug!("Updating Pages...");
+        let start = Instant::now();
+        let mut counts = Vec::with_capacity(self.pages.len());
+        {
+            let (config, model) = (&self.config, &self.model);
+            self.pages
+                .par_iter_mut()
+                .weight_max()
+                .map(|page| page.update_signal(config, model))
+                .collect_into(&mut counts);
+        }
+        for (active, delivered) in counts {
+            report.active_cells += active;
+            report.signals_delivered += delivered;
+        }
+
+        if self.config.plasticity == Plasticity::Hebbian {
+            let mut mail = mem::replace(&mut self.contact_mail, Mail::new(0));
+            for (i, page) in self.pages.iter_mut().enumerate() {
+                page.take_remote_contacts(mail.sent(i));
             }
+            mail.route(|c| Some((self.page_index(c.x, c.y, c.z), c)));
+            let config = &self.config;
+            self.pages
+                .par_iter_mut()
+                .zip(mail.inboxes())
+                .weight_max()
+                .for_each(|(page, inbox)| page.receive_contacts(config, inbox));
+            mail.recycle();
+            self.contact_mail = mail;
+        }
 
+        if self.config.plasticity == Plasticity::Stdp {
+            let fired = self.fired_cells();
+            let mut deliveries = Vec::with_capacity(self.pages.len());
+            self.pages
+                .par_iter()
+                .map(|page| page.get_deliveries())
+                .collect_into(&mut deliveries);
+            self.apply_stdp(&fired, &deliveries.concat());
         }
 
+        self.step += 1;
+        report.update_time = start.elapsed();
+        report
+    }
 
-        debug!("Active cells after growth: {}", active_cells);
 
-        debug!("Updating Pages...");
-        self.pages
-            .par_iter_mut()
-            .weight_max()
-            .for_each(|page| page.update());
+    fn route_change(&self, mut change: RemoteChange<N>) -> Option<(usize, RemoteChange<N>)> {
+        debug!("Absolute change position: ({},{},{})", change.x, change.y, change.z);
+        self.resolve((change.x, change.y, change.z), change.travel_direction)
+            .map(|((x, y, z), travel_direction)| {
+                change.x = x;
+                change.y = y;
+                change.z = z;
+                change.travel_direction = travel_direction;
+                (self.page_index(x, y, z), change)
+            })
+    }
 
-        active_cells
+    fn route_signal(&self, mut signal: RemoteSignal<N>) -> Option<(usize, RemoteSignal<N>)> {
+        debug!("Absolute signal position: ({},{},{})", signal.x, signal.y, signal.z);
+        self.resolve((signal.x, signal.y, signal.z), signal.travel_direction)
+            .map(|((x, y, z), travel_direction)| {
+                signal.x = x;
+                signal.y = y;
+                signal.z = z;
+                signal.travel_direction = travel_direction;
+                (self.page_index(x, y, z), signal)
+            })
     }
 
-    pub fn signal(&mut self) {
-        loop {
-            let active_cells = self.signal_step();
+    fn contains(&self, x: u32, y: u32, z: u32) -> bool {
+        x < self.width && y < self.height && z < self.depth
+    }
 
-            if active_cells == 0 {
-                break;
+    // Pages hand over coordinates one step past their edge, which may lie outside the
+    // grid (the low edges wrap to u32::MAX).  Returns where the change or signal actually
+    // lands and the direction it is travelling in by then
+    fn resolve(&self,
+               (x, y, z): (u32, u32, u32),
+               travel_direction: N)
+               -> Option<((u32, u32, u32), N)> {
+        if self.contains(x, y, z) {
+            return Some(((x, y, z), travel_direction));
+        }
+
+        match self.config.boundary {
+            Boundary::Absorbing => None,
+            Boundary::Toroidal => {
+                let wrap = |v: u32, limit: u32| {
+                    if v == ::std::u32::MAX {
+                        limit - 1
+                    } else if v >= limit {
+                        0
+                    } else {
+                        v
+                    }
+                };
+                Some(((wrap(x, self.width), wrap(y, self.height), wrap(z, self.depth)),
+                      travel_direction))
+            }
+            Boundary::Reflecting => {
+                // Mirrored about the edge cell, so the growth heads back into the grid
+                let reflect = |v: u32, limit: u32| {
+                    if v == ::std::u32::MAX {
+                        1
+                    } else if v >= limit {
+                        limit - 2
+                    } else {
+                        v
+                    }
+                };
+                Some(((reflect(x, self.width),
+                       reflect(y, self.height),
+                       reflect(z, self.depth)),
+                      !travel_direction))
             }
         }
     }
 
-    pub fn signal_step(&mut self) -> u32 {
-        debug!("Processing signals...");
+    // The cell adjacent to (x, y, z) in the given direction, taking the boundary into
+    // account
+    pub fn neighbour_at(&self, x: u32, y: u32, z: u32, direction: N) -> Option<(u32, u32, u32)> {
+        let next = direction.step(x, y, z);
 
-        self.pages
-            .par_iter_mut()
-            .weight_max()
-            .for_each(|page| page.signal());
+        if self.contains(next.0, next.1, next.2) {
+            return Some(next);
+        }
+        match self.config.boundary {
+            Boundary::Toroidal => self.resolve(next, direction).map(|(position, _)| position),
+            Boundary::Absorbing | Boundary::Reflecting => None,
+        }
+    }
 
-        for i in 0..self.pages.len() {
+    // Where the page with the given index starts, pages going row by row and then layer
+    // by layer
+    fn page_offset(i: u32, pages_x: u32, pages_y: u32) -> (u32, u32, u32) {
+        ((i % pages_x) * N::page_width(),
+         ((i / pages_x) % pages_y) * N::page_width(),
+         (i / (pages_x * pages_y)) * N::page_depth())
+    }
 
-            let signals = self.pages[i].get_remote_signal().clone();
+    fn page_index(&self, x: u32, y: u32, z: u32) -> usize {
+        let (width, depth) = (N::page_width(), N::page_depth());
+        let layer = self.pages_x * self.pages_y;
+        (x / width + (y / width) * self.pages_x + (z / depth) * layer) as usize
+    }
 
-            if signals.is_empty() {
-                continue;
-            }
+    // Allocates the page if it is still a placeholder
+    fn get_mut_page(&mut self, x: u32, y: u32, z: u32) -> &mut Page<N> {
+        let i = self.page_index(x, y, z);
+        debug!("get_mut_page: ({},{},{}) -> {}", x, y, z, i);
+        let page = &mut self.pages[i];
+        page.allocate(&self.config);
+        page
+    }
 
-            debug!("Remote signal to process: {}", signals.len());
+    pub fn width(&self) -> u32 {
+        self.width
+    }
 
-            for s in signals {
-                debug!("Absolute signal position: ({},{})", s.x, s.y);
-                if !(s.x > 0 && s.x < self.dimension && s.y > 0 && s.y < self.dimension) {
-                    debug!("x > 1 {}", s.x > 0);
-                    debug!("x < dimension - 1{}", s.x < self.dimension);
-                    debug!("y > 1 {}", s.y > 0);
-                    debug!("y < dimension - 1 {}", s.y < self.dimension);
+    pub fn height(&self) -> u32 {
+        self.height
+    }
 
-                    continue;
-                }
-                self.get_mut_page(s.x, s.y)
-                    .add_signal(s.x % PAGE_WIDTH, s.y % PAGE_WIDTH, s.strength, s.stim);
-            }
-        }
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
 
-        debug!("Updating Pages...");
-        self.pages
-            .par_iter_mut()
-            .weight_max()
-            .map(|page| page.update_signal())
-            .sum()
+    pub fn cell_at(&self, x: u32, y: u32, z: u32) -> &Cell {
+        let (width, depth) = (N::page_width(), N::page_depth());
+        self.pages[self.page_index(x, y, z)].get_cell(x % width, y % width, z % depth)
     }
 
+    pub fn cell_at_mut(&mut self, x: u32, y: u32, z: u32) -> &mut Cell {
+        let (width, depth) = (N::page_width(), N::page_depth());
+        self.get_mut_page(x, y, z).get_mut_cell(x % width, y % width, z % depth)
+    }
 
-    fn get_mut_page(&mut self, x: u32, y: u32) -> &mut Page {
-        let i = x / PAGE_WIDTH + ((y / PAGE_WIDTH) * self.pages_per_side);
-        debug!("get_mut_page: ({},{}) -> {}", x, y, i);
-        &mut self.pages[i as usize]
+    pub fn fired_cells(&self) -> Vec<(u32, u32, u32)> {
+        let mut fired = Vec::new();
+        for page in &self.pages {
+            fired.extend(page.get_fired());
+        }
+        fired
     }
 
-    pub fn get_cell(&self, x: u32, y: u32) -> &Cell {
-        let i = x / PAGE_WIDTH + ((y / PAGE_WIDTH) * self.pages_per_side);
-        self.pages[i as usize].get_cell(x % PAGE_WIDTH, y % PAGE_WIDTH)
+    pub fn is_firing_at(&self, x: u32, y: u32, z: u32) -> bool {
+        let (width, depth) = (N::page_width(), N::page_depth());
+        self.pages[self.page_index(x, y, z)].is_firing(x % width, y % width, z % depth)
     }
 
-    fn get_mut_cell(&mut self, x: u32, y: u32) -> &mut Cell {
-        let i = x / PAGE_WIDTH + ((y / PAGE_WIDTH) * self.pages_per_side);
-        self.pages[i as usize].get_mut_cell(x % PAGE_WIDTH, y % PAGE_WIDTH)
+    pub fn set_input_at(&mut self, x: u32, y: u32, z: u32, sig: u8) {
+        let (width, depth) = (N::page_width(), N::page_depth());
+        self.get_mut_page(x, y, z).set_input(x % width, y % width, z % depth, sig);
     }
 
-    pub fn set_input(&mut self, x: u32, y: u32, sig: u8) {
-        self.get_mut_page(x, y).set_input(x % PAGE_WIDTH, y % PAGE_WIDTH, sig);
+    // Leaves the raster for the caller to fill in
+    pub fn memory_usage(&self) -> MemoryReport {
+        let mut pages = Vec::with_capacity(self.pages.len());
+        self.pages
+            .par_iter()
+            .map(|page| page.memory_usage())
+            .collect_into(&mut pages);
+
+        MemoryReport {
+            pages: pages,
+            plasticity: self.stdp.memory_usage(),
+            routing: self.change_mail.memory_usage() + self.signal_mail.memory_usage() +
+                     self.contact_mail.memory_usage(),
+            raster: 0,
+        }
     }
+
+    pub fn state_hash(&self) -> u64 {
+        self.pages.iter().fold(FNV_OFFSET, |hash, page| page.state_hash(hash))
+    }
+}
+
+
+// The genes the page at the given offset starts out with
+pub fn page_genes(config: &CajalConfig, offset_x: u32, offset_y: u32) -> Vec<Cell> {
+    Page::<Gate>::genes(config, offset_x, offset_y, 0)
 }
 
 
 impl Default for Grid {
     fn default() -> Grid {
-        Grid::new(10, 0.05, &[1, 2, 3, 4])
+        Grid::new(&CajalConfig::default())
     }
 }
 
 
 #[cfg(test)]
 mod test {
+    use std::io::Cursor;
     use super::Grid;
+    use config::{Boundary, CajalConfig};
+    use evolve::Genome;
+    use grid::{CellType, Chromosome, Gate, Gate3D};
+    use growth::ChromosomeGrowth;
+    use model::CoDi;
 
     #[test]
     fn grid_default_params() {
         let _ = Grid::default();
     }
+
+    fn grid(boundary: Boundary) -> Grid {
+        Grid::new(&CajalConfig {
+            size: 1,
+            density: 0.0,
+            boundary: boundary,
+            ..CajalConfig::default()
+        })
+    }
+
+    #[test]
+    fn resolve_boundaries() {
+        let max = ::std::u32::MAX;
+
+        let absorbing = grid(Boundary::Absorbing);
+        assert!(absorbing.resolve((10, 255, 0), Gate::North) == Some(((10, 255, 0), Gate::North)));
+        assert!(absorbing.resolve((10, 256, 0), Gate::North).is_none());
+        assert!(absorbing.resolve((max, 10, 0), Gate::West).is_none());
+
+        let toroidal = grid(Boundary::Toroidal);
+        assert!(toroidal.resolve((10, 256, 0), Gate::North) == Some(((10, 0, 0), Gate::North)));
+        assert!(toroidal.resolve((max, 10, 0), Gate::West) == Some(((255, 10, 0), Gate::West)));
+        assert!(toroidal.neighbour(0, 10, Gate::West) == Some((255, 10)));
+        assert!(absorbing.neighbour(0, 10, Gate::West).is_none());
+
+        let reflecting = grid(Boundary::Reflecting);
+        assert!(reflecting.resolve((10, 256, 0), Gate::North) == Some(((10, 254, 0), Gate::South)));
+        assert!(reflecting.resolve((max, 10, 0), Gate::West) == Some(((1, 10, 0), Gate::East)));
+    }
+
+    #[test]
+    fn rectangular() {
+        let mut grid = Grid::new(&CajalConfig {
+            width: Some(3),
+            height: Some(1),
+            density: 0.0,
+            ..CajalConfig::default()
+        });
+        assert!(grid.width() == 768 && grid.height() == 256);
+        assert!(grid.pages.len() == 3);
+
+        grid.get_mut_cell(700, 100).set_cell_type(CellType::Body);
+        assert!(grid.pages[2].get_cell(700 - 512, 100, 0).get_cell_type() == CellType::Body);
+        assert!(grid.resolve((767, 256, 0), Gate::North).is_none());
+        assert!(grid.resolve((768, 255, 0), Gate::East).is_none());
+    }
+
+    #[test]
+    fn growth_allocates_pages() {
+        let mut grid = Grid::new(&CajalConfig {
+            width: Some(2),
+            height: Some(1),
+            density: 0.0,
+            ..CajalConfig::default()
+        });
+        assert!(grid.pages.iter().all(|p| !p.is_allocated()));
+
+        {
+            let cell = grid.get_mut_cell(255, 10);
+            cell.set_cell_type(CellType::Axon);
+            cell.set_chromosome(Chromosome::East);
+        }
+        grid.set_input(255, 10, 0);
+        assert!(grid.pages[0].is_allocated() && !grid.pages[1].is_allocated());
+
+        grid.grow_step();
+        assert!(grid.pages[1].is_allocated());
+        assert!(grid.get_cell(256, 10).get_cell_type() == CellType::Axon);
+    }
+
+    #[test]
+    fn sparse_pages_stay_lazy() {
+        let grid = Grid::new(&CajalConfig {
+            size: 32,
+            density: 0.000001,
+            ..CajalConfig::default()
+        });
+
+        // About one page in fifteen gets a body, the others are never allocated
+        let allocated: Vec<_> = grid.pages.iter().filter(|p| p.is_allocated()).collect();
+        assert!(allocated.len() > 20 && allocated.len() < 200);
+        for page in allocated {
+            let bodies = (0..256 * 256)
+                             .filter(|i| {
+                                 let cell = page.get_cell(i % 256, i / 256, 0);
+                                 cell.get_cell_type() == CellType::Body
+                             })
+                             .count();
+            assert!(bodies == 1);
+        }
+    }
+
+    #[test]
+    fn sparse_genomes_stay_lazy() {
+        let config = CajalConfig { size: 4, density: 0.000001, ..CajalConfig::default() };
+        let genome = Genome::from_config(&config).unwrap();
+        let grid = Grid::with_genome(&config, &genome, CoDi, ChromosomeGrowth);
+        let lazy = grid.pages.iter().position(|p| !p.is_allocated()).unwrap();
+        let (x, y, _) = grid.pages[lazy].offset();
+
+        // Once growth reaches it, even after a snapshot, the page gets the genome's genes
+        let mut buf = Vec::new();
+        grid.save(&mut buf).unwrap();
+        let mut grid: Grid = Grid::load(&mut Cursor::new(buf), CoDi, ChromosomeGrowth).unwrap();
+        assert!(!grid.pages[lazy].is_allocated());
+        grid.get_mut_cell(x, y);
+        assert!(grid.pages[lazy].is_allocated());
+        for i in 0..256 * 256 {
+            let (dx, dy) = (i % 256, i / 256);
+            assert!(grid.get_cell(x + dx, y + dy).raw() == genome.get(x + dx, y + dy).raw());
+        }
+    }
+
+    #[test]
+    fn toroidal_growth_wraps() {
+        let mut grid = grid(Boundary::Toroidal);
+        {
+            let cell = grid.get_mut_cell(0, 10);
+            cell.set_cell_type(CellType::Axon);
+            cell.set_chromosome(Chromosome::West);
+        }
+        // Only marks the cell active, a zero signal doesn't affect growth
+        grid.set_input(0, 10, 0);
+        grid.grow_step();
+
+        let cell = grid.get_cell(255, 10);
+        assert!(cell.get_cell_type() == CellType::Axon);
+        assert!(cell.get_gate() == Gate::East);
+    }
+
+    #[test]
+    fn step_report() {
+        let mut grid = Grid::new(&CajalConfig {
+            size: 2,
+            density: 0.01,
+            seed: vec![1, 2, 3, 4],
+            ..CajalConfig::default()
+        });
+        let report = grid.grow_step();
+        assert!(report.active_cells > 0 && report.grown() == report.active_cells);
+        assert!(report.local_changes > 0 && report.pages_touched == 4);
+        assert!(report.signals_delivered == 0 && report.bodies_fired == 0);
+
+        grid.grow();
+        for i in 1..2 * 256 - 1 {
+            grid.set_input(i, i, 63);
+        }
+        let report = grid.signal_step();
+        assert!(report.signals_delivered > 0 && report.grown() == 0);
+        assert!(report.bodies_fired as usize == grid.get_fired().len());
+    }
+
+    #[test]
+    fn empty_grid_is_lazy() {
+        let mut grid = Grid::new(&CajalConfig {
+            width: Some(64),
+            height: Some(64),
+            density: 0.0,
+            ..CajalConfig::default()
+        });
+        assert!(grid.memory_usage().sum().total() == 0);
+        grid.grow();
+        assert!(grid.get_cell(10000, 10000).get_cell_type() == CellType::Empty);
+    }
+
+    #[test]
+    fn grow_3d() {
+        let config = CajalConfig {
+            size: 2,
+            depth: Some(2),
+            density: 0.01,
+            seed: vec![1, 2, 3, 4],
+            ..CajalConfig::default()
+        };
+        let mut grid: Grid<CoDi, ChromosomeGrowth, Gate3D> = Grid::with_rules(&config,
+                                                                              CoDi,
+                                                                              ChromosomeGrowth);
+        grid.grow();
+
+        // Something must have grown up out of the bottom layer of pages
+        let dimension = grid.width();
+        let mut crossed = false;
+        for x in 0..dimension {
+            for y in 0..dimension {
+                let cell = grid.cell_at(x, y, 32);
+                if cell.get_cell_type() != CellType::Empty && cell.get_gate_3d() == Gate3D::Down {
+                    crossed = true;
+                }
+            }
+        }
+        assert!(crossed);
+
+        for i in 1..dimension - 1 {
+            grid.set_input_at(i, i, i, 63);
+        }
+        let mut active = 0;
+        for _ in 0..10 {
+            active += grid.signal_step().active_cells;
+        }
+        assert!(active > 0);
+    }
+
+    fn fire(grid: &mut Grid, (x, y): (u32, u32), cell_type: CellType, gate: Gate) {
+        let cell = grid.get_mut_cell(x, y);
+        cell.set_cell_type(cell_type);
+        cell.set_gate(gate);
+        cell.set_threshold(1);
+        cell.set_strength(5);
+        cell.set_stim(true);
+        grid.set_input(x, y, 10);
+    }
+
+    fn place(grid: &mut Grid, (x, y): (u32, u32), cell_type: CellType, gate: Gate) {
+        let cell = grid.get_mut_cell(x, y);
+        cell.set_cell_type(cell_type);
+        cell.set_gate(gate);
+        cell.set_threshold(63);
+    }
+
+    #[test]
+    fn axons_relay_through_their_gate() {
+        let mut grid = grid(Boundary::Absorbing);
+        fire(&mut grid, (10, 10), CellType::Axon, Gate::West);
+        // Gate facing the sender, and one facing away
+        place(&mut grid, (11, 10), CellType::Axon, Gate::West);
+        place(&mut grid, (10, 11), CellType::Axon, Gate::North);
+        grid.signal_step();
+
+        assert!(grid.get_cell(11, 10).get_signal() == 5);
+        assert!(grid.get_cell(10, 11).get_signal() == 0);
+        assert!(grid.get_cell(10, 10).get_signal() == 0);
+    }
+
+    #[test]
+    fn signal_across_pages() {
+        let mut grid = Grid::new(&CajalConfig {
+            width: Some(2),
+            height: Some(1),
+            density: 0.0,
+            ..CajalConfig::default()
+        });
+        fire(&mut grid, (255, 10), CellType::Body, Gate::East);
+        place(&mut grid, (256, 10), CellType::Dendrite, Gate::West);
+        fire(&mut grid, (255, 20), CellType::Axon, Gate::West);
+        place(&mut grid, (256, 20), CellType::Axon, Gate::West);
+        grid.signal_step();
+
+        assert!(grid.get_cell(256, 10).get_signal() == 5 && grid.is_firing(255, 10));
+        assert!(grid.get_cell(256, 20).get_signal() == 5 && grid.is_firing(255, 20));
+        assert!(grid.get_cell(255, 10).get_signal() == 0);
+        assert!(grid.get_cell(255, 20).get_signal() == 0);
+    }
+
+    #[test]
+    fn signal_across_boundaries() {
+        let mut toroidal = grid(Boundary::Toroidal);
+        fire(&mut toroidal, (255, 10), CellType::Axon, Gate::West);
+        place(&mut toroidal, (0, 10), CellType::Axon, Gate::West);
+        toroidal.signal_step();
+        assert!(toroidal.get_cell(0, 10).get_signal() == 5);
+
+        // Bounced back off the edge, so it arrives through the side facing the edge
+        let mut reflecting = grid(Boundary::Reflecting);
+        fire(&mut reflecting, (255, 10), CellType::Body, Gate::East);
+        place(&mut reflecting, (254, 10), CellType::Dendrite, Gate::East);
+        reflecting.signal_step();
+        assert!(reflecting.get_cell(254, 10).get_signal() == 5);
+    }
 }
@@ -0,0 +1,250 @@
+use num::FromPrimitive;
+use std::fmt::Debug;
+use std::ops::Not;
+use rand::{Rand, Rng};
+
+use super::cell::{Cell, Chromosome, Chromosome3D, Gate, Gate3D};
+use super::zorder;
+use super::super::{PAGE_SIZE, PAGE_WIDTH};
+
+const PAGE_WIDTH_3D: u32 = 32;
+
+static DIRECTIONS: &'static [Gate] = &[Gate::North, Gate::South, Gate::East, Gate::West];
+static DIRECTIONS_3D: &'static [Gate3D] = &[Gate3D::North,
+                                            Gate3D::South,
+                                            Gate3D::East,
+                                            Gate3D::West,
+                                            Gate3D::Up,
+                                            Gate3D::Down];
+
+// The shape of a grid, implemented by the gate of each kind of grid.  Pages are cubes of
+// `page_width()` cells along x and y and `page_depth()` along z, so flat pages are one cell
+// deep.  Positions are always (x, y, z), with z staying 0 on a flat grid
+pub trait Neighbourhood: Copy + PartialEq + Debug + Not<Output = Self> + Rand + Send + Sync +
+                         'static {
+    // Every direction growth and signal can take, in the order pages go through them
+    fn directions() -> &'static [Self];
+
+    fn page_width() -> u32;
+    fn page_depth() -> u32;
+
+    fn page_size() -> u32 {
+        Self::page_width() * Self::page_width() * Self::page_depth()
+    }
+
+    fn to_index(x: u32, y: u32, z: u32) -> u32;
+    fn to_position(index: u32) -> (u32, u32, u32);
+
+    // Raw value as stored in a cell and in snapshots
+    fn bits(self) -> u32;
+    fn from_bits(bits: u32) -> Option<Self>;
+
+    // The gate of a cell, read and written as this kind of gate
+    fn of(cell: &Cell) -> Self;
+    fn set(self, cell: &mut Cell);
+
+    // Whether the chromosome of the cell lets it grow this way
+    fn in_chromosome(self, cell: &Cell) -> bool;
+    // Chromosome weights in the config are indexed by these bit patterns
+    fn chromosomes() -> u32;
+    fn random_chromosome<R: Rng>(rng: &mut R) -> u32;
+    fn set_chromosome(cell: &mut Cell, bits: u32);
+
+    // Whether raw cell data from outside fits this kind of grid
+    fn is_valid(cell: &Cell) -> bool;
+
+    // The first dendrites of a body grow at right angles to its first axons
+    fn dendrite(self) -> Self;
+
+    // One step from (x, y, z).  Steps below zero wrap around to u32::MAX
+    fn step(self, x: u32, y: u32, z: u32) -> (u32, u32, u32);
+}
+
+impl Neighbourhood for Gate {
+    fn directions() -> &'static [Gate] {
+        DIRECTIONS
+    }
+
+    fn page_width() -> u32 {
+        PAGE_WIDTH
+    }
+
+    fn page_depth() -> u32 {
+        1
+    }
+
+    fn page_size() -> u32 {
+        PAGE_SIZE
+    }
+
+    fn to_index(x: u32, y: u32, _: u32) -> u32 {
+        zorder::xy_to_z(x, y)
+    }
+
+    fn to_position(index: u32) -> (u32, u32, u32) {
+        let (x, y) = zorder::z_to_xy(index);
+        (x, y, 0)
+    }
+
+    fn bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Gate> {
+        Gate::from_u32(bits)
+    }
+
+    fn of(cell: &Cell) -> Gate {
+        cell.get_gate()
+    }
+
+    fn set(self, cell: &mut Cell) {
+        cell.set_gate(self);
+    }
+
+    fn in_chromosome(self, cell: &Cell) -> bool {
+        cell.get_chromosome().contains(Chromosome::from(self))
+    }
+
+    fn chromosomes() -> u32 {
+        16
+    }
+
+    fn random_chromosome<R: Rng>(rng: &mut R) -> u32 {
+        rng.gen::<Chromosome>() as u32
+    }
+
+    fn set_chromosome(cell: &mut Cell, bits: u32) {
+        cell.set_chromosome(Chromosome::from_u32(bits).unwrap());
+    }
+
+    fn is_valid(cell: &Cell) -> bool {
+        cell.is_valid()
+    }
+
+    fn dendrite(self) -> Gate {
+        match self {
+            Gate::North => Gate::West,
+            Gate::West => Gate::South,
+            Gate::South => Gate::East,
+            Gate::East => Gate::North,
+        }
+    }
+
+    fn step(self, x: u32, y: u32, z: u32) -> (u32, u32, u32) {
+        match self {
+            Gate::North => (x, y + 1, z),
+            Gate::South => (x, y.wrapping_sub(1), z),
+            Gate::East => (x + 1, y, z),
+            Gate::West => (x.wrapping_sub(1), y, z),
+        }
+    }
+}
+
+impl Neighbourhood for Gate3D {
+    fn directions() -> &'static [Gate3D] {
+        DIRECTIONS_3D
+    }
+
+    fn page_width() -> u32 {
+        PAGE_WIDTH_3D
+    }
+
+    fn page_depth() -> u32 {
+        PAGE_WIDTH_3D
+    }
+
+    fn to_index(x: u32, y: u32, z: u32) -> u32 {
+        zorder::xyz_to_z(x, y, z)
+    }
+
+    fn to_position(index: u32) -> (u32, u32, u32) {
+        zorder::z_to_xyz(index)
+    }
+
+    fn bits(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bits(bits: u32) -> Option<Gate3D> {
+        Gate3D::from_u32(bits)
+    }
+
+    fn of(cell: &Cell) -> Gate3D {
+        cell.get_gate_3d()
+    }
+
+    fn set(self, cell: &mut Cell) {
+        cell.set_gate_3d(self);
+    }
+
+    fn in_chromosome(self, cell: &Cell) -> bool {
+        cell.get_chromosome_3d().contains(self)
+    }
+
+    fn chromosomes() -> u32 {
+        64
+    }
+
+    fn random_chromosome<R: Rng>(rng: &mut R) -> u32 {
+        rng.gen::<Chromosome3D>().bits() as u32
+    }
+
+    fn set_chromosome(cell: &mut Cell, bits: u32) {
+        cell.set_chromosome_3d(Chromosome3D::from_bits(bits as u8));
+    }
+
+    fn is_valid(cell: &Cell) -> bool {
+        cell.is_valid_3d()
+    }
+
+    // Vertical axons get dendrites along y
+    fn dendrite(self) -> Gate3D {
+        match self {
+            Gate3D::North => Gate3D::West,
+            Gate3D::West => Gate3D::South,
+            Gate3D::South => Gate3D::East,
+            Gate3D::East => Gate3D::North,
+            Gate3D::Up => Gate3D::North,
+            Gate3D::Down => Gate3D::South,
+        }
+    }
+
+    fn step(self, x: u32, y: u32, z: u32) -> (u32, u32, u32) {
+        match self {
+            Gate3D::North => (x, y + 1, z),
+            Gate3D::South => (x, y.wrapping_sub(1), z),
+            Gate3D::East => (x + 1, y, z),
+            Gate3D::West => (x.wrapping_sub(1), y, z),
+            Gate3D::Up => (x, y, z + 1),
+            Gate3D::Down => (x, y, z.wrapping_sub(1)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::Neighbourhood;
+    use grid::{Cell, Gate, Gate3D};
+
+    #[test]
+    fn index_round_trip() {
+        assert!(Gate::page_size() == 65536 && Gate3D::page_size() == 32768);
+        assert!(Gate::to_position(Gate::to_index(200, 17, 0)) == (200, 17, 0));
+        assert!(Gate3D::to_position(Gate3D::to_index(31, 2, 17)) == (31, 2, 17));
+    }
+
+    #[test]
+    fn steps() {
+        assert!(Gate::West.step(0, 5, 0) == (::std::u32::MAX, 5, 0));
+        assert!(Gate3D::Up.step(1, 2, 3) == (1, 2, 4));
+        assert!(Gate3D::Down.step(1, 2, 0) == (1, 2, ::std::u32::MAX));
+
+        let mut cell = Cell::new();
+        Gate3D::Down.set(&mut cell);
+        assert!(Gate3D::of(&cell) == Gate3D::Down);
+        Gate3D::set_chromosome(&mut cell, 0b110000);
+        assert!(Gate3D::Up.in_chromosome(&cell) && !Gate3D::North.in_chromosome(&cell));
+    }
+}
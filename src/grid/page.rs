@@ -1,43 +1,72 @@
 
 use roaring::RoaringBitmap;
 use std::collections::HashMap;
+use std::f32;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Arc;
+use num::FromPrimitive;
 use rand::distributions::{IndependentSample, Range};
 use rand::{Rng, SeedableRng, StdRng};
 
 pub use super::cell::{Cell, Chromosome, CellType, Gate};
-use super::zorder;
-use super::super::{ReportMemory, PAGE_SIZE, PAGE_WIDTH};
+use super::cell::EMPTY;
+use super::neighbourhood::Neighbourhood;
+use super::plasticity;
+use super::hash_u32;
+use snapshot::{self, Snapshot};
+use config::{CajalConfig, Plasticity};
+use growth::GrowthRule;
+use memory::{self, MemoryUsage};
+use model::{Input, NeuronModel};
 use self::ChangeType::{Remote, Local, NoChange};
 
-static CARDINAL_DIRECTIONS: &'static [Gate] = &[Gate::North, Gate::South, Gate::East, Gate::West];
-
-pub struct Page {
+#[derive(Clone)]
+pub struct Page<N = Gate> {
     cells: Vec<Cell>,
     active: RoaringBitmap<u32>,
     changes: HashMap<u32, Cell>,
-    remote_changes: Vec<RemoteChange>,
-    local_signal: Vec<LocalSignal>,
-    remote_signal: Vec<RemoteSignal>,
+    remote_changes: Vec<RemoteChange<N>>,
+    local_signal: Vec<LocalSignal<N>>,
+    remote_signal: Vec<RemoteSignal<N>>,
+    // Whatever the neuron model keeps for a cell between steps, for every cell where
+    // that isn't 0
+    states: HashMap<u32, u64>,
+    // Hebbian updates for axons on other pages that signalled into this one
+    remote_contacts: Vec<RemoteContact>,
+    // Bodies that crossed threshold and (axon, receiving cell) of every delivery from an
+    // axon during the last signal step.  Collected for STDP and spike rasters, never
+    // persisted
+    fired: Vec<u32>,
+    deliveries: Vec<((u32, u32, u32), u32)>,
+    // Every cell that passed signal on during the last signal step, for output regions
+    firing: RoaringBitmap<u32>,
+    // What a placeholder gets allocated with when its genes came from a genome rather
+    // than the config
+    genes: Option<Arc<Vec<Cell>>>,
     offset_x: u32,
     offset_y: u32,
+    offset_z: u32,
 }
 
 #[derive(Debug, Copy, Clone)]
-pub struct RemoteChange {
+pub struct RemoteChange<N = Gate> {
     pub x: u32,
     pub y: u32,
+    pub z: u32,
     pub cell: Cell,
-    pub travel_direction: Gate,
+    pub travel_direction: N,
     pub stim: bool,
 }
 
-enum ChangeType {
+enum ChangeType<N> {
     Local((u32, Cell)),
-    Remote(RemoteChange),
+    Remote(RemoteChange<N>),
     NoChange,
 }
 
-impl ChangeType {
+impl<N> ChangeType<N> {
     pub fn is_some(&self) -> bool {
         match *self {
             NoChange => false,
@@ -47,132 +76,486 @@ impl ChangeType {
 }
 
 #[derive(Debug, Copy, Clone)]
-enum SignalType {
-    Local(LocalSignal),
-    Remote(RemoteSignal),
+enum SignalType<N> {
+    Local(LocalSignal<N>),
+    Remote(RemoteSignal<N>),
     NoSignal,
 }
 
 #[derive(Debug, Copy, Clone)]
-pub struct RemoteSignal {
+pub struct RemoteSignal<N = Gate> {
     pub x: u32,
     pub y: u32,
+    pub z: u32,
     pub strength: u8,
     pub stim: bool,
     pub origin_cell_type: CellType,
+    pub travel_direction: N,
+    // Potentiation level of the sender and where in the grid it sits
+    pub level: u8,
+    pub sender: (u32, u32, u32),
 }
 
 #[derive(Debug, Copy, Clone)]
-pub struct LocalSignal {
+pub struct LocalSignal<N = Gate> {
     pub x: u32,
     pub y: u32,
+    pub z: u32,
     pub to_index: usize,
     pub strength: u8,
     pub stim: bool,
     pub origin_cell_type: CellType,
+    pub travel_direction: N,
+    pub level: u8,
+    // Where in the grid the sender sits when that is on another page.  (x, y, z) is the
+    // receiving cell then
+    pub sender: Option<(u32, u32, u32)>,
+}
+
+// A delivery from an axon on another page that counts towards changing its potentiation
+#[derive(Debug, Copy, Clone)]
+pub struct RemoteContact {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+    pub potentiate: bool,
 }
 
 
 
-impl ReportMemory for Page {
-    fn memory(&self) -> u32 {
-        (self.cells.len() as u32 * 16) +
-        (self.active.len() as u32 * 8) +  // <-- This is not true!
-        ((self.changes.len() as u32 + self.changes.capacity() as u32) * 8) // Rough approximation
+impl<N: Neighbourhood> Snapshot for Page<N> {
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(snapshot::write_u32(writer, self.offset_x));
+        try!(snapshot::write_u32(writer, self.offset_y));
+        try!(snapshot::write_u32(writer, self.offset_z));
+
+        let cells: Vec<u32> = self.cells.iter().map(|c| c.raw()).collect();
+        try!(snapshot::write_u32_slice(writer, &cells));
+        let genes: Vec<u32> = self.genes.iter().flat_map(|g| g.iter()).map(|c| c.raw()).collect();
+        try!(snapshot::write_u32_slice(writer, &genes));
+
+        let active: Vec<u32> = self.active.iter().collect();
+        try!(snapshot::write_u32_slice(writer, &active));
+
+        // Sorted so that identical pages always produce identical snapshots
+        let mut changes: Vec<(&u32, &Cell)> = self.changes.iter().collect();
+        changes.sort_by_key(|&(k, _)| *k);
+        try!(snapshot::write_u32(writer, changes.len() as u32));
+        for (k, v) in changes {
+            try!(snapshot::write_u32(writer, *k));
+            try!(snapshot::write_u32(writer, v.raw()));
+        }
+
+        try!(snapshot::write_u32(writer, self.remote_changes.len() as u32));
+        for c in &self.remote_changes {
+            try!(snapshot::write_u32(writer, c.x));
+            try!(snapshot::write_u32(writer, c.y));
+            try!(snapshot::write_u32(writer, c.z));
+            try!(snapshot::write_u32(writer, c.cell.raw()));
+            try!(snapshot::write_u8(writer, c.travel_direction.bits() as u8));
+            try!(snapshot::write_bool(writer, c.stim));
+        }
+
+        try!(snapshot::write_u32(writer, self.local_signal.len() as u32));
+        for s in &self.local_signal {
+            try!(snapshot::write_u32(writer, s.x));
+            try!(snapshot::write_u32(writer, s.y));
+            try!(snapshot::write_u32(writer, s.z));
+            try!(snapshot::write_u32(writer, s.to_index as u32));
+            try!(snapshot::write_u8(writer, s.strength));
+            try!(snapshot::write_bool(writer, s.stim));
+            try!(snapshot::write_u8(writer, s.origin_cell_type as u8));
+            try!(snapshot::write_u8(writer, s.travel_direction.bits() as u8));
+            try!(snapshot::write_u8(writer, s.level));
+            try!(snapshot::write_bool(writer, s.sender.is_some()));
+            if let Some(sender) = s.sender {
+                try!(snapshot::write_position(writer, sender));
+            }
+        }
+
+        try!(snapshot::write_u32(writer, self.remote_signal.len() as u32));
+        for s in &self.remote_signal {
+            try!(snapshot::write_u32(writer, s.x));
+            try!(snapshot::write_u32(writer, s.y));
+            try!(snapshot::write_u32(writer, s.z));
+            try!(snapshot::write_u8(writer, s.strength));
+            try!(snapshot::write_bool(writer, s.stim));
+            try!(snapshot::write_u8(writer, s.origin_cell_type as u8));
+            try!(snapshot::write_u8(writer, s.travel_direction.bits() as u8));
+            try!(snapshot::write_u8(writer, s.level));
+            try!(snapshot::write_position(writer, s.sender));
+        }
+
+        let mut states: Vec<(&u32, &u64)> = self.states.iter().collect();
+        states.sort_by_key(|&(k, _)| *k);
+        try!(snapshot::write_u32(writer, states.len() as u32));
+        for (k, v) in states {
+            try!(snapshot::write_u32(writer, *k));
+            try!(snapshot::write_u64(writer, *v));
+        }
+
+        Ok(())
+    }
+
+    fn load<R: Read>(reader: &mut R) -> io::Result<Page<N>> {
+        let offset_x = try!(snapshot::read_u32(reader));
+        let offset_y = try!(snapshot::read_u32(reader));
+        let offset_z = try!(snapshot::read_u32(reader));
+
+        let mut cells = Vec::new();
+        for raw in try!(snapshot::read_u32_vec(reader)) {
+            cells.push(try!(check_cell::<N>(Cell::from_raw(raw))));
+        }
+        if !cells.is_empty() && cells.len() != N::page_size() as usize {
+            return Err(snapshot::invalid_data("page has the wrong number of cells"));
+        }
+
+        let mut genes = Vec::new();
+        for raw in try!(snapshot::read_u32_vec(reader)) {
+            genes.push(try!(check_cell::<N>(Cell::from_raw(raw))));
+        }
+        if !genes.is_empty() && (genes.len() != N::page_size() as usize || !cells.is_empty()) {
+            return Err(snapshot::invalid_data("page has the wrong number of genes"));
+        }
+
+        let mut active: RoaringBitmap<u32> = RoaringBitmap::new();
+        for index in try!(snapshot::read_u32_vec(reader)) {
+            active.insert(try!(check_index::<N>(index)));
+        }
+
+        let len = try!(snapshot::read_u32(reader));
+        let mut changes = HashMap::with_capacity(snapshot::capacity(len));
+        for _ in 0..len {
+            let k = try!(check_index::<N>(try!(snapshot::read_u32(reader))));
+            let v = try!(check_cell::<N>(Cell::from_raw(try!(snapshot::read_u32(reader)))));
+            changes.insert(k, v);
+        }
+
+        let len = try!(snapshot::read_u32(reader));
+        let mut remote_changes = Vec::with_capacity(snapshot::capacity(len));
+        for _ in 0..len {
+            remote_changes.push(RemoteChange {
+                x: try!(snapshot::read_u32(reader)),
+                y: try!(snapshot::read_u32(reader)),
+                z: try!(snapshot::read_u32(reader)),
+                cell: try!(check_cell::<N>(Cell::from_raw(try!(snapshot::read_u32(reader))))),
+                travel_direction: try!(read_gate::<N, R>(reader)),
+                stim: try!(snapshot::read_bool(reader)),
+            });
+        }
+
+        let len = try!(snapshot::read_u32(reader));
+        let mut local_signal = Vec::with_capacity(snapshot::capacity(len));
+        for _ in 0..len {
+            let signal = LocalSignal {
+                x: try!(snapshot::read_u32(reader)),
+                y: try!(snapshot::read_u32(reader)),
+                z: try!(snapshot::read_u32(reader)),
+                to_index: try!(check_index::<N>(try!(snapshot::read_u32(reader)))) as usize,
+                strength: try!(snapshot::read_u8(reader)),
+                stim: try!(snapshot::read_bool(reader)),
+                origin_cell_type: try!(read_cell_type(reader)),
+                travel_direction: try!(read_gate::<N, R>(reader)),
+                level: try!(snapshot::read_u8(reader)),
+                sender: match try!(snapshot::read_bool(reader)) {
+                    true => Some(try!(snapshot::read_position(reader))),
+                    false => None,
+                },
+            };
+            if signal.x >= N::page_width() || signal.y >= N::page_width() ||
+               signal.z >= N::page_depth() {
+                return Err(snapshot::invalid_data("signal outside of the page"));
+            }
+            local_signal.push(signal);
+        }
+
+        let len = try!(snapshot::read_u32(reader));
+        let mut remote_signal = Vec::with_capacity(snapshot::capacity(len));
+        for _ in 0..len {
+            remote_signal.push(RemoteSignal {
+                x: try!(snapshot::read_u32(reader)),
+                y: try!(snapshot::read_u32(reader)),
+                z: try!(snapshot::read_u32(reader)),
+                strength: try!(snapshot::read_u8(reader)),
+                stim: try!(snapshot::read_bool(reader)),
+                origin_cell_type: try!(read_cell_type(reader)),
+                travel_direction: try!(read_gate::<N, R>(reader)),
+                level: try!(snapshot::read_u8(reader)),
+                sender: try!(snapshot::read_position(reader)),
+            });
+        }
+
+        let len = try!(snapshot::read_u32(reader));
+        let mut states = HashMap::with_capacity(snapshot::capacity(len));
+        for _ in 0..len {
+            let k = try!(check_index::<N>(try!(snapshot::read_u32(reader))));
+            let v = try!(snapshot::read_u64(reader));
+            if v == 0 {
+                return Err(snapshot::invalid_data("empty model state"));
+            }
+            states.insert(k, v);
+        }
+
+        // Only pages that hold cells can have anything going on locally
+        if cells.is_empty() &&
+           (!active.is_empty() || !changes.is_empty() || !local_signal.is_empty() ||
+            !states.is_empty()) {
+            return Err(snapshot::invalid_data("unallocated page with cell state"));
+        }
+
+        Ok(Page {
+            cells: cells,
+            active: active,
+            changes: changes,
+            remote_changes: remote_changes,
+            local_signal: local_signal,
+            remote_signal: remote_signal,
+            states: states,
+            remote_contacts: Vec::new(),
+            fired: Vec::new(),
+            deliveries: Vec::new(),
+            firing: RoaringBitmap::new(),
+            genes: match genes.is_empty() {
+                true => None,
+                false => Some(Arc::new(genes)),
+            },
+            offset_x: offset_x,
+            offset_y: offset_y,
+            offset_z: offset_z,
+        })
     }
 }
 
-impl Page {
-    pub fn new(density: f32, offset_x: u32, offset_y: u32, seed: &[usize]) -> Page {
-        debug!("Creating new Page with {} density.", density);
-        // let mut rng = thread_rng();
-        let mut final_seed = vec![offset_x as usize, offset_y as usize];
-        final_seed.extend(seed);
-        let mut rng: StdRng = SeedableRng::from_seed(final_seed.as_slice());
+struct WeightedIndex {
+    cumulative: Vec<u32>,
+    range: Range<u32>,
+}
 
-        let mut cells: Vec<Cell> = Vec::with_capacity(PAGE_SIZE as usize);
-        let range_threshold = Range::new(0, 4);
+impl WeightedIndex {
+    fn new(weights: &[u32]) -> WeightedIndex {
+        let mut total = 0;
+        let cumulative = weights.iter()
+                                .map(|w| {
+                                    total += *w;
+                                    total
+                                })
+                                .collect();
+        WeightedIndex {
+            cumulative: cumulative,
+            range: Range::new(0, total),
+        }
+    }
 
-        for _ in 0..PAGE_SIZE as usize {
-            let mut cell = Cell::new();
-            cell.set_chromosome(rng.gen());
-            cell.set_gate(rng.gen());
-            cell.set_threshold(range_threshold.ind_sample(&mut rng));
-            cells.push(cell);
+    fn sample<R: Rng>(&self, rng: &mut R) -> u32 {
+        let target = self.range.ind_sample(rng);
+        match self.cumulative.iter().position(|c| target < *c) {
+            Some(i) => i as u32,
+            None => unreachable!(),
         }
+    }
+}
 
-        let mut bitmap: RoaringBitmap<u32> = RoaringBitmap::new();
+// Draws the genes of a cell: its chromosome, gate and threshold
+pub struct GeneSampler<N = Gate> {
+    chromosomes: Option<WeightedIndex>,
+    gates: Option<WeightedIndex>,
+    threshold: Range<u8>,
+    neighbourhood: PhantomData<N>,
+}
+
+impl<N: Neighbourhood> GeneSampler<N> {
+    pub fn new(config: &CajalConfig) -> GeneSampler<N> {
+        GeneSampler {
+            chromosomes: config.chromosome_weights.as_ref().map(|w| WeightedIndex::new(w)),
+            gates: config.gate_weights.as_ref().map(|w| WeightedIndex::new(w)),
+            threshold: Range::new(config.threshold_min, config.threshold_max),
+            neighbourhood: PhantomData,
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Cell {
+        let mut cell = Cell::new();
+        let chromosome = match self.chromosomes {
+            Some(ref w) => w.sample(rng),
+            None => N::random_chromosome(rng),
+        };
+        N::set_chromosome(&mut cell, chromosome);
+        let gate: N = match self.gates {
+            Some(ref w) => N::from_bits(w.sample(rng)).unwrap(),
+            None => rng.gen(),
+        };
+        gate.set(&mut cell);
+        cell.set_threshold(self.threshold.ind_sample(rng));
+        cell
+    }
+}
+
+fn read_gate<N: Neighbourhood, R: Read>(reader: &mut R) -> io::Result<N> {
+    match N::from_bits(try!(snapshot::read_u8(reader)) as u32) {
+        Some(g) => Ok(g),
+        None => Err(snapshot::invalid_data("invalid gate")),
+    }
+}
+
+fn check_index<N: Neighbourhood>(index: u32) -> io::Result<u32> {
+    if index >= N::page_size() {
+        return Err(snapshot::invalid_data("cell index outside of the page"));
+    }
+    Ok(index)
+}
 
-        // TODO roll this into the initialization loop
-        let active_cells: u32 = (PAGE_SIZE as f32 * density).round() as u32;
+// Everything about a queued signal but where it goes, for `state_hash`
+fn signal_bits<N: Neighbourhood>(strength: u8, stim: bool, origin: CellType, direction: N) -> u32 {
+    (strength as u32) | (stim as u32) << 8 | (origin as u32) << 9 | direction.bits() << 16
+}
+
+fn check_cell<N: Neighbourhood>(cell: Cell) -> io::Result<Cell> {
+    if !N::is_valid(&cell) {
+        return Err(snapshot::invalid_data("invalid cell"));
+    }
+    Ok(cell)
+}
+
+fn read_cell_type<R: Read>(reader: &mut R) -> io::Result<CellType> {
+    match CellType::from_u8(try!(snapshot::read_u8(reader))) {
+        Some(ct) => Ok(ct),
+        None => Err(snapshot::invalid_data("invalid cell type")),
+    }
+}
+
+impl<N: Neighbourhood> Page<N> {
+    pub fn new(config: &CajalConfig, offset_x: u32, offset_y: u32, offset_z: u32) -> Page<N> {
+        debug!("Creating new Page with {} density.", config.density);
+
+        let active_cells = Page::<N>::body_count(config, (offset_x, offset_y, offset_z));
         debug!("Active cells in this Page: {}", active_cells);
 
-        let range_cells = Range::new(1, PAGE_WIDTH - 1);
+        // Pages without any bodies stay unallocated until growth reaches them
+        if active_cells == 0 {
+            return Page::placeholder(offset_x, offset_y, offset_z);
+        }
 
+        let mut rng = Page::<N>::rng(config, offset_x, offset_y, offset_z);
+        let cells = Page::<N>::generate_cells(config, &mut rng);
+        Page::populate(config, (offset_x, offset_y, offset_z), cells, active_cells, rng)
+    }
+
+    // Builds a page around genes that came from elsewhere, which pages without bodies
+    // hold on to until growth reaches them.  Bodies are placed from a rng of their own
+    // rather than the page rng, which only gets to them after the genes, so they depend on
+    // the config alone and no genes get drawn just to be dropped
+    pub fn from_genes(config: &CajalConfig,
+                      offset_x: u32,
+                      offset_y: u32,
+                      offset_z: u32,
+                      genes: Arc<Vec<Cell>>)
+                      -> Page<N> {
+        let offset = (offset_x, offset_y, offset_z);
+        let active_cells = Page::<N>::body_count(config, offset);
+        if active_cells == 0 {
+            let mut page = Page::placeholder(offset_x, offset_y, offset_z);
+            page.genes = Some(genes);
+            return page;
+        }
+
+        let final_seed = Page::<N>::seed(config, offset, &[::std::usize::MAX - 1]);
+        let rng = SeedableRng::from_seed(final_seed.as_slice());
+        Page::populate(config, offset, (*genes).clone(), active_cells, rng)
+    }
+
+    // Pages expecting less than one body get one with that probability instead, so sparse
+    // grids still have bodies while most of their pages stay unallocated.  The draw has a
+    // rng of its own, the page rng is only set up once the page gets allocated
+    fn body_count(config: &CajalConfig, offset: (u32, u32, u32)) -> u32 {
+        let expected = N::page_size() as f32 * config.density;
+        if expected >= 1.0 {
+            return expected.round() as u32;
+        }
+        if expected <= 0.0 {
+            return 0;
+        }
+
+        let final_seed = Page::<N>::seed(config, offset, &[::std::usize::MAX]);
+        let mut rng: StdRng = SeedableRng::from_seed(final_seed.as_slice());
+        if rng.next_f32() < expected { 1 } else { 0 }
+    }
+
+    // The genes `new` draws for the page at the given offset
+    pub fn genes(config: &CajalConfig, offset_x: u32, offset_y: u32, offset_z: u32) -> Vec<Cell> {
+        let mut rng = Page::<N>::rng(config, offset_x, offset_y, offset_z);
+        Page::<N>::generate_cells(config, &mut rng)
+    }
+
+    // Places bodies and grows their first axons and dendrites
+    fn populate(config: &CajalConfig,
+                (offset_x, offset_y, offset_z): (u32, u32, u32),
+                mut cells: Vec<Cell>,
+                active_cells: u32,
+                mut rng: StdRng)
+                -> Page<N> {
+        let mut bitmap: RoaringBitmap<u32> = RoaringBitmap::new();
+
+        let range_cells = Range::new(config.body_margin, N::page_width() - config.body_margin);
+        let range_strength = Range::new(config.strength_min, config.strength_max);
+
+        // The cells each body set up, the body first
+        let mut placed = Vec::with_capacity(active_cells as usize);
         for _ in 0..active_cells {
             let (x, y) = (range_cells.ind_sample(&mut rng),
                           range_cells.ind_sample(&mut rng));
-            let index = zorder::xy_to_z(x, y);
+            let z = if N::page_depth() > 1 {
+                range_cells.ind_sample(&mut rng)
+            } else {
+                0
+            };
+            let index = N::to_index(x, y, z);
 
             cells[index as usize].set_cell_type(CellType::Body);
-            let stim: bool = rng.gen();
+            // An even split keeps drawing a plain bool, so default layouts stay as they were
+            let stim: bool = if (config.stim_ratio - 0.5).abs() < f32::EPSILON {
+                rng.gen()
+            } else {
+                rng.next_f32() < config.stim_ratio
+            };
             cells[index as usize].set_stim(stim);
 
-            let axon_direction: Gate = cells[index as usize].get_gate();
-            let dendrite_direction = match axon_direction {
-                Gate::North => Gate::West,
-                Gate::West => Gate::South,
-                Gate::South => Gate::East,
-                Gate::East => Gate::North,
-            };
+            let axon_direction = N::of(&cells[index as usize]);
+            let dendrite_direction = axon_direction.dendrite();
 
             let secondary_axon = !axon_direction;
             let secondary_dendrite = !dendrite_direction;
 
-            if let Local((target, change)) = Page::grow_local(&mut cells,
-                                                              x,
-                                                              y,
-                                                              CellType::Axon,
-                                                              axon_direction,
-                                                              stim) {
-                cells[target as usize].set_cell_type(change.get_cell_type());
-                cells[target as usize].set_gate(change.get_gate());
-                cells[target as usize].set_stim(stim);
-                bitmap.insert(target);
-            }
-            if let Local((target, change)) = Page::grow_local(&mut cells,
-                                                              x,
-                                                              y,
-                                                              CellType::Axon,
-                                                              secondary_axon,
-                                                              stim) {
-                cells[target as usize].set_cell_type(change.get_cell_type());
-                cells[target as usize].set_gate(change.get_gate());
-                cells[target as usize].set_stim(stim);
-                bitmap.insert(target);
+            let branches = [(CellType::Axon, axon_direction, stim),
+                            (CellType::Axon, secondary_axon, stim),
+                            (CellType::Dendrite, dendrite_direction, false),
+                            (CellType::Dendrite, secondary_dendrite, false)];
+            let mut grown = vec![index];
+            for &(cell_type, direction, stim) in &branches {
+                if let Local((target, change)) = Page::grow_local(&mut cells,
+                                                                  x,
+                                                                  y,
+                                                                  z,
+                                                                  cell_type,
+                                                                  direction,
+                                                                  stim,
+                                                                  0) {
+                    cells[target as usize].set_cell_type(change.get_cell_type());
+                    N::of(&change).set(&mut cells[target as usize]);
+                    cells[target as usize].set_stim(stim);
+                    bitmap.insert(target);
+                    grown.push(target);
+                }
             }
+            placed.push(grown);
+        }
 
-            if let Local((target, change)) = Page::grow_local(&mut cells,
-                                                              x,
-                                                              y,
-                                                              CellType::Dendrite,
-                                                              dendrite_direction,
-                                                              false) {
-                cells[target as usize].set_cell_type(change.get_cell_type());
-                cells[target as usize].set_gate(change.get_gate());
-                cells[target as usize].set_stim(false);
-                bitmap.insert(target);
-            }
-            if let Local((target, change)) = Page::grow_local(&mut cells,
-                                                              x,
-                                                              y,
-                                                              CellType::Dendrite,
-                                                              secondary_dendrite,
-                                                              false) {
-                cells[target as usize].set_cell_type(change.get_cell_type());
-                cells[target as usize].set_gate(change.get_gate());
-                cells[target as usize].set_stim(false);
-                bitmap.insert(target);
+        // Strengths are only drawn once every body is in place, so that they don't move
+        // bodies or stim around.  Going in the same order leaves cells that several bodies
+        // grew into with the strength of the last one
+        for grown in placed {
+            let strength = range_strength.ind_sample(&mut rng);
+            for index in grown {
+                cells[index as usize].set_strength(strength);
             }
         }
 
@@ -182,13 +565,97 @@ impl Page {
             changes: HashMap::new(),
             offset_x: offset_x,
             offset_y: offset_y,
+            offset_z: offset_z,
             remote_changes: Vec::with_capacity(32),
             remote_signal: Vec::with_capacity(32),
             local_signal: Vec::with_capacity(32),
+            states: HashMap::new(),
+            remote_contacts: Vec::new(),
+            fired: Vec::new(),
+            deliveries: Vec::new(),
+            firing: RoaringBitmap::new(),
+            genes: None,
+        }
+    }
+
+    fn placeholder(offset_x: u32, offset_y: u32, offset_z: u32) -> Page<N> {
+        Page {
+            cells: Vec::new(),
+            active: RoaringBitmap::new(),
+            changes: HashMap::new(),
+            offset_x: offset_x,
+            offset_y: offset_y,
+            offset_z: offset_z,
+            remote_changes: Vec::new(),
+            remote_signal: Vec::new(),
+            local_signal: Vec::new(),
+            states: HashMap::new(),
+            remote_contacts: Vec::new(),
+            fired: Vec::new(),
+            deliveries: Vec::new(),
+            firing: RoaringBitmap::new(),
+            genes: None,
         }
     }
 
-    pub fn grow(&mut self) {
+    // Flat pages leave z out of their seeds, so they keep the layouts they always had
+    fn seed(config: &CajalConfig, offset: (u32, u32, u32), extra: &[usize]) -> Vec<usize> {
+        let mut final_seed = vec![offset.0 as usize, offset.1 as usize];
+        if N::page_depth() > 1 {
+            final_seed.push(offset.2 as usize);
+        }
+        final_seed.extend(extra);
+        final_seed.extend(&config.seed);
+        final_seed
+    }
+
+    fn rng(config: &CajalConfig, offset_x: u32, offset_y: u32, offset_z: u32) -> StdRng {
+        let final_seed = Page::<N>::seed(config, (offset_x, offset_y, offset_z), &[]);
+        SeedableRng::from_seed(final_seed.as_slice())
+    }
+
+    // Seeded from the generation rather than kept around, so growth resumes identically
+    // from a snapshot
+    fn growth_rng(config: &CajalConfig, offset: (u32, u32, u32), generation: u64) -> StdRng {
+        let final_seed = Page::<N>::seed(config, offset, &[generation as usize]);
+        SeedableRng::from_seed(final_seed.as_slice())
+    }
+
+    // Always the first thing drawn from a page's rng, so a placeholder allocated later
+    // ends up with exactly the cells it would have had from the start
+    fn generate_cells(config: &CajalConfig, rng: &mut StdRng) -> Vec<Cell> {
+        let sampler = GeneSampler::<N>::new(config);
+        (0..N::page_size()).map(|_| sampler.sample(rng)).collect()
+    }
+
+    pub fn offset(&self) -> (u32, u32, u32) {
+        (self.offset_x, self.offset_y, self.offset_z)
+    }
+
+    pub fn is_allocated(&self) -> bool {
+        !self.cells.is_empty()
+    }
+
+    pub fn allocate(&mut self, config: &CajalConfig) {
+        if !self.is_allocated() {
+            debug!("Allocating page ({},{},{})",
+                   self.offset_x,
+                   self.offset_y,
+                   self.offset_z);
+            self.cells = match self.genes.take() {
+                Some(genes) => Arc::try_unwrap(genes).unwrap_or_else(|genes| (*genes).clone()),
+                None => {
+                    let mut rng = Page::<N>::rng(config,
+                                                 self.offset_x,
+                                                 self.offset_y,
+                                                 self.offset_z);
+                    Page::<N>::generate_cells(config, &mut rng)
+                }
+            };
+        }
+    }
+
+    pub fn grow<G: GrowthRule<N>>(&mut self, config: &CajalConfig, rule: &G, generation: u64) {
 
         debug!("Growing {} cells.", self.active.len());
         debug!("Changelist size: {}", self.changes.len());
@@ -196,23 +663,29 @@ impl Page {
             return;
         }
 
+        let mut rng = Page::<N>::growth_rng(config, self.offset(), generation);
+        let offset = self.offset();
         let mut cells = &mut self.cells;
 
         for index in self.active.iter() {
-            let (x, y) = zorder::z_to_xy(index);
-            let cell_type = cells[index as usize].get_cell_type();
+            let (x, y, z) = N::to_position(index);
             let stim = cells[index as usize].get_stim();
-
-            for direction in CARDINAL_DIRECTIONS {
-                if cells[index as usize].get_chromosome().contains(Chromosome::from(*direction)) {
+            let strength = cells[index as usize].get_strength();
+
+            for direction in N::directions() {
+                let grown = rule.grow(&cells[index as usize],
+                                      *direction,
+                                      Page::<N>::local_target(x, y, z, *direction)
+                                          .map(|target| &cells[target as usize]),
+                                      &mut rng);
+                if let Some(cell_type) = grown {
                     let change = Page::process_chromosome_direction(*direction,
                                                                     &mut cells,
-                                                                    x,
-                                                                    y,
-                                                                    self.offset_x,
-                                                                    self.offset_y,
+                                                                    (x, y, z),
+                                                                    offset,
                                                                     cell_type,
-                                                                    stim);
+                                                                    stim,
+                                                                    strength);
 
                     match change {
                         ChangeType::Local((target, change)) => {
@@ -225,58 +698,114 @@ impl Page {
                     }
                 }
             }
+
+            // Cells only grow once, so from here on the chromosome bits of an axon count
+            // its potentiation
+            if config.plasticity != Plasticity::Off &&
+               cells[index as usize].get_cell_type() == CellType::Axon {
+                plasticity::reset(&mut cells[index as usize]);
+            }
         }
 
         debug!("After growth: Changelist size: {}", self.changes.len());
 
     }
 
-    pub fn get_remote_changes(&self) -> &Vec<RemoteChange> {
-        &self.remote_changes
+    // The index of the cell next to (x, y, z) in `direction`, if it lies on this page
+    fn local_target(x: u32, y: u32, z: u32, direction: N) -> Option<u32> {
+        let (x, y, z) = direction.step(x, y, z);
+        if x < N::page_width() && y < N::page_width() && z < N::page_depth() {
+            Some(N::to_index(x, y, z))
+        } else {
+            None
+        }
+    }
+
+    // Hands over the growth bound for other pages in exchange for `queue`, which has to
+    // be empty
+    pub fn take_remote_changes(&mut self, queue: &mut Vec<RemoteChange<N>>) {
+        mem::swap(&mut self.remote_changes, queue);
+    }
+
+    // Applies growth other pages sent here, with absolute coordinates that have already
+    // been resolved against the boundary, and leaves the inbox empty.  Allocates the page
+    // if anything arrives
+    pub fn receive_changes(&mut self,
+                           config: &CajalConfig,
+                           inbox: &mut [(usize, Vec<RemoteChange<N>>)]) {
+        if inbox.iter().all(|&(_, ref changes)| changes.is_empty()) {
+            return;
+        }
+
+        self.allocate(config);
+        for &mut (_, ref mut changes) in inbox {
+            for c in changes.drain(..) {
+                self.add_change(c.x % N::page_width(),
+                                c.y % N::page_width(),
+                                c.z % N::page_depth(),
+                                c.cell,
+                                c.travel_direction,
+                                c.stim);
+            }
+        }
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            cells: memory::vec_bytes(&self.cells) +
+                   self.genes.as_ref().map_or(0, |genes| memory::vec_bytes(genes)),
+            bitmaps: memory::bitmap_bytes(&self.active) + memory::bitmap_bytes(&self.firing),
+            changes: memory::map_bytes(&self.changes) + memory::vec_bytes(&self.remote_changes),
+            signals: memory::vec_bytes(&self.local_signal) +
+                     memory::vec_bytes(&self.remote_signal) +
+                     memory::map_bytes(&self.states) + memory::vec_bytes(&self.fired) +
+                     memory::vec_bytes(&self.deliveries) +
+                     memory::vec_bytes(&self.remote_contacts),
+        }
     }
 
-    pub fn get_active_cell_count(&self) -> u32 {
+    pub fn get_change_count(&self) -> u32 {
         self.changes.len() as u32
     }
 
-    fn process_chromosome_direction(travel_direction: Gate,
+    pub fn get_fired_count(&self) -> u32 {
+        self.fired.len() as u32
+    }
+
+    // Whether the page had active cells or was sent changes or signals this step
+    pub fn is_busy(&self) -> bool {
+        !self.active.is_empty() || !self.changes.is_empty() || !self.local_signal.is_empty()
+    }
+
+    fn process_chromosome_direction(travel_direction: N,
                                     cells: &mut Vec<Cell>,
-                                    x: u32,
-                                    y: u32,
-                                    offset_x: u32,
-                                    offset_y: u32,
+                                    (x, y, z): (u32, u32, u32),
+                                    offset: (u32, u32, u32),
                                     cell_type: CellType,
-                                    stim: bool)
-                                    -> ChangeType {
+                                    stim: bool,
+                                    strength: u8)
+                                    -> ChangeType<N> {
 
-        match (travel_direction, x, y) {
-            (Gate::North, _, y) if y < PAGE_WIDTH - 1 => {
-                Page::grow_local(cells, x, y, cell_type, travel_direction, stim)
-            }
-            (Gate::South, _, y) if y > 0 => {
-                Page::grow_local(cells, x, y, cell_type, travel_direction, stim)
-            }
-            (Gate::East, x, _) if x < PAGE_WIDTH - 1 => {
-                Page::grow_local(cells, x, y, cell_type, travel_direction, stim)
+        match Page::<N>::local_target(x, y, z, travel_direction) {
+            Some(_) => {
+                Page::grow_local(cells, x, y, z, cell_type, travel_direction, stim, strength)
             }
-            (Gate::West, x, _) if x > 0 => {
-                Page::grow_local(cells, x, y, cell_type, travel_direction, stim)
-            }
-            (_, _, _) => {
-                Page::create_remote_change(x,
-                                           y,
-                                           offset_x,
-                                           offset_y,
+            None => {
+                Page::create_remote_change((x, y, z),
+                                           offset,
                                            cell_type,
                                            travel_direction,
-                                           stim)
+                                           stim,
+                                           strength)
             }
         }
     }
 
-    pub fn update(&mut self) {
+    // Returns how many cells grew, indexed by cell type
+    pub fn update(&mut self) -> [u32; 4] {
 
         debug!("Updating {} cells.", self.changes.len());
+        let mut grown = [0; 4];
 
         // Clear out the active cell bitmap, and add the cells we just grew
         debug!("Stale active cells: {}", self.active.len());
@@ -284,13 +813,15 @@ impl Page {
         debug!("Cleared active cells: {}", self.active.len());
 
         if self.changes.is_empty() {
-            return;
+            return grown;
         }
 
         for (k, v) in &self.changes {
+            grown[v.get_cell_type() as usize] += 1;
             self.cells[*k as usize].set_cell_type(v.get_cell_type());
-            self.cells[*k as usize].set_gate(v.get_gate());
+            N::of(v).set(&mut self.cells[*k as usize]);
             self.cells[*k as usize].set_stim(v.get_stim());
+            self.cells[*k as usize].set_strength(v.get_strength());
             self.active.insert(*k);
         }
 
@@ -298,55 +829,60 @@ impl Page {
         self.remote_changes.clear();
         debug!("New active cells: {}", self.active.len());
         debug!("New Change list: {}", self.changes.len());
+        grown
     }
 
-    pub fn add_change(&mut self, x: u32, y: u32, cell: Cell, travel_direction: Gate, stim: bool) {
-        debug!("Attempting to add remote change: ({}, {})", x, y);
+    pub fn add_change(&mut self,
+                      x: u32,
+                      y: u32,
+                      z: u32,
+                      cell: Cell,
+                      travel_direction: N,
+                      stim: bool) {
+        debug!("Attempting to add remote change: ({}, {}, {})", x, y, z);
         let cell_type = cell.get_cell_type();
 
-        let target = zorder::xy_to_z(x, y);
+        let target = N::to_index(x, y, z);
         if self.cells[target as usize].get_cell_type() == CellType::Empty {
             debug!("Inserting external change.");
             self.changes.insert(target,
-                                Page::create_change(cell_type, !travel_direction, stim));
+                                Page::create_change(cell_type,
+                                                    !travel_direction,
+                                                    stim,
+                                                    cell.get_strength()));
         }
     }
 
-    fn create_remote_change(x: u32,
-                            y: u32,
-                            offset_x: u32,
-                            offset_y: u32,
+    fn create_remote_change((x, y, z): (u32, u32, u32),
+                            (offset_x, offset_y, offset_z): (u32, u32, u32),
                             cell_type: CellType,
-                            travel_direction: Gate,
-                            stim: bool)
-                            -> ChangeType {
-        debug!("create_remote_change: ({},{}) offsets: ({},{}) {:?}",
+                            travel_direction: N,
+                            stim: bool,
+                            strength: u8)
+                            -> ChangeType<N> {
+        debug!("create_remote_change: ({},{},{}) offsets: ({},{},{}) {:?}",
                x,
                y,
+               z,
                offset_x,
                offset_y,
+               offset_z,
                travel_direction);
 
-        if (offset_x == 0 && travel_direction == Gate::West) ||
-           (offset_y == 0 && travel_direction == Gate::South) {
-            return NoChange;
-        }
-
-        let (x, y) = match travel_direction {
-            Gate::North => (offset_x + x, offset_y + y + 1),
-            Gate::South => (offset_x + x, offset_y + y - 1),
-            Gate::West => (offset_x + x - 1, offset_y + y),
-            Gate::East => (offset_x + x + 1, offset_y + y),
-        };
-        debug!("create_remote_change: new: ({},{}) {:?}",
+        // Coordinates past the grid edge are left for the grid to resolve, so the low
+        // edges wrap around to u32::MAX here
+        let (x, y, z) = travel_direction.step(offset_x + x, offset_y + y, offset_z + z);
+        debug!("create_remote_change: new: ({},{},{}) {:?}",
                x,
                y,
+               z,
                travel_direction);
 
         Remote(RemoteChange {
             x: x,
             y: y,
-            cell: Page::create_change(cell_type, !travel_direction, stim),
+            z: z,
+            cell: Page::create_change(cell_type, !travel_direction, stim, strength),
             travel_direction: travel_direction,
             stim: stim,
         })
@@ -356,100 +892,160 @@ impl Page {
     fn grow_local(cells: &mut Vec<Cell>,
                   x: u32,
                   y: u32,
+                  z: u32,
                   cell_type: CellType,
-                  travel_direction: Gate,
-                  stim: bool)
-                  -> ChangeType {
-        assert!((x > PAGE_WIDTH - 1 && travel_direction == Gate::East) != true);
-        assert!((y > PAGE_WIDTH - 1 && travel_direction == Gate::North) != true);
-
-        let (target, gate) = Page::calc_target(x, y, travel_direction);
+                  travel_direction: N,
+                  stim: bool,
+                  strength: u8)
+                  -> ChangeType<N> {
+        let target = match Page::<N>::local_target(x, y, z, travel_direction) {
+            Some(target) => target,
+            None => panic!("growing off the page from ({},{},{})", x, y, z),
+        };
 
         if cells[target as usize].get_cell_type() == CellType::Empty {
-            Local((target, Page::create_change(cell_type, gate, stim)))
+            Local((target, Page::create_change(cell_type, !travel_direction, stim, strength)))
         } else {
             NoChange
         }
     }
 
-
-    fn calc_target(x: u32, y: u32, direction: Gate) -> (u32, Gate) {
-        trace!("calc_target: ({},{}) -> {:?}", x, y, direction);
-        match direction {
-            Gate::North => (zorder::xy_to_z(x, y + 1), Gate::South),
-            Gate::South => (zorder::xy_to_z(x, y - 1), Gate::North),
-            Gate::East => (zorder::xy_to_z(x + 1, y), Gate::West),
-            Gate::West => (zorder::xy_to_z(x - 1, y), Gate::East),
-        }
-    }
-
-    fn create_change(cell_type: CellType, gate: Gate, stim: bool) -> Cell {
+    fn create_change(cell_type: CellType, gate: N, stim: bool, strength: u8) -> Cell {
         // TODO reuse from a pool of allocated cells?
         let mut change = Cell::new();
         change.set_cell_type(cell_type);
-        change.set_gate(gate);
+        gate.set(&mut change);
         change.set_stim(stim);
+        change.set_strength(strength);
         change
     }
 
-    pub fn get_cell(&self, x: u32, y: u32) -> &Cell {
-        let z = zorder::xy_to_z(x, y);
-        &self.cells[z as usize]
+    pub fn get_cell(&self, x: u32, y: u32, z: u32) -> &Cell {
+        if !self.is_allocated() {
+            return &EMPTY;
+        }
+        &self.cells[N::to_index(x, y, z) as usize]
     }
 
-    pub fn get_mut_cell(&mut self, x: u32, y: u32) -> &mut Cell {
-        let z = zorder::xy_to_z(x, y);
-        &mut self.cells[z as usize]
+    pub fn get_mut_cell(&mut self, x: u32, y: u32, z: u32) -> &mut Cell {
+        &mut self.cells[N::to_index(x, y, z) as usize]
+    }
+
+    pub fn state_hash(&self, hash: u64) -> u64 {
+        let hash = self.cells.iter().fold(hash, |hash, cell| hash_u32(hash, cell.raw()));
+        let hash = self.genes
+                       .iter()
+                       .flat_map(|genes| genes.iter())
+                       .fold(hash, |hash, gene| hash_u32(hash, gene.raw()));
+        let hash = hash_u32(hash, self.active.len());
+        let hash = self.active.iter().fold(hash, hash_u32);
+
+        let mut states: Vec<(&u32, &u64)> = self.states.iter().collect();
+        states.sort_by_key(|&(k, _)| *k);
+        let hash = hash_u32(hash, states.len() as u32);
+        let hash = states.into_iter().fold(hash, |hash, (k, s)| {
+            hash_u32(hash_u32(hash_u32(hash, *k), *s as u32), (*s >> 32) as u32)
+        });
+
+        // Whatever is queued for the next step matters as much as the cells themselves
+        let mut changes: Vec<(&u32, &Cell)> = self.changes.iter().collect();
+        changes.sort_by_key(|&(k, _)| *k);
+        let hash = hash_u32(hash, changes.len() as u32);
+        let hash = changes.into_iter()
+                          .fold(hash, |hash, (k, c)| hash_u32(hash_u32(hash, *k), c.raw()));
+
+        let hash = hash_u32(hash, self.remote_changes.len() as u32);
+        let hash = self.remote_changes.iter().fold(hash, |hash, c| {
+            let hash = hash_u32(hash_u32(hash_u32(hash, c.x), c.y), c.z);
+            hash_u32(hash_u32(hash, c.cell.raw()),
+                     c.travel_direction.bits() | (c.stim as u32) << 16)
+        });
+
+        let hash = hash_u32(hash, self.local_signal.len() as u32);
+        let hash = self.local_signal.iter().fold(hash, |hash, s| {
+            let hash = hash_u32(hash_u32(hash_u32(hash, s.x), s.y), s.z);
+            let hash = hash_u32(hash, s.to_index as u32);
+            let hash = hash_u32(hash,
+                                signal_bits(s.strength, s.stim, s.origin_cell_type,
+                                            s.travel_direction) | (s.level as u32) << 24);
+            match s.sender {
+                Some((x, y, z)) => hash_u32(hash_u32(hash_u32(hash_u32(hash, 1), x), y), z),
+                None => hash_u32(hash, 0),
+            }
+        });
+
+        let hash = hash_u32(hash, self.remote_signal.len() as u32);
+        self.remote_signal.iter().fold(hash, |hash, s| {
+            let hash = hash_u32(hash_u32(hash_u32(hash, s.x), s.y), s.z);
+            let hash = hash_u32(hash,
+                                signal_bits(s.strength, s.stim, s.origin_cell_type,
+                                            s.travel_direction) | (s.level as u32) << 24);
+            hash_u32(hash_u32(hash_u32(hash, s.sender.0), s.sender.1), s.sender.2)
+        })
     }
 
 
 
     // ---------------------------------
 
-    pub fn set_input(&mut self, x: u32, y: u32, sig: u8) {
-        debug!("Adding artificial signal to ({}, {}) @ {}", x, y, sig);
-        let z = zorder::xy_to_z(x, y);
-        self.cells[z as usize].set_signal(sig);
-        self.active.insert(z);
+    pub fn set_input(&mut self, x: u32, y: u32, z: u32, sig: u8) {
+        debug!("Adding artificial signal to ({}, {}, {}) @ {}", x, y, z, sig);
+        let index = N::to_index(x, y, z);
+        self.cells[index as usize].set_signal(sig);
+        self.active.insert(index);
     }
 
-    pub fn signal(&mut self) {
+    pub fn signal<M: NeuronModel<N>>(&mut self, config: &CajalConfig, model: &M) {
+        self.fired.clear();
+        self.firing.clear();
+        self.fire(config, model);
+        self.tick(config, model);
+    }
+
+    fn fire<M: NeuronModel<N>>(&mut self, config: &CajalConfig, model: &M) {
 
         debug!("Processing signals for {} cells.", self.active.len());
         if self.active.is_empty() == true {
             return;
         }
 
+        let offset = self.offset();
         for index in self.active.iter() {
 
             let threshold = self.cells[index as usize].get_threshold();
             let signal = self.cells[index as usize].get_signal();
             debug!("Threshold: {}, Signal: {}", threshold, signal);
 
-            if signal < threshold {
+            if !model.fires(&self.cells[index as usize]) {
+                Page::<N>::check(&mut self.states,
+                                 &mut self.cells[index as usize],
+                                 index,
+                                 false,
+                                 config,
+                                 model);
                 continue;
             }
 
-            let (x, y) = zorder::z_to_xy(index);
+            let position = N::to_position(index);
+            if signal > 0 {
+                self.firing.insert(index);
+            }
 
             match self.cells[index as usize].get_cell_type() {
                 CellType::Axon => {
                     debug!("Signal landed on Axon");
 
-                    let targets = Chromosome::from(self.cells[index as usize].get_gate()).invert();
-                    debug!("Axon targets: {:?}", targets);
-
-                    for direction in CARDINAL_DIRECTIONS {
-                        if targets.contains(Chromosome::from(*direction)) {
+                    // Everywhere but back out through the gate
+                    let gate = N::of(&self.cells[index as usize]);
+                    for direction in N::directions() {
+                        if *direction != gate {
                             debug!("Target contains {:?}", direction);
 
                             let sig = Page::process_signal(*direction,
                                                            &mut self.cells,
                                                            index as usize,
-                                                           x,
-                                                           y,
-                                                           self.offset_x,
-                                                           self.offset_y);
+                                                           position,
+                                                           offset);
 
                             Page::persist_signal(&mut self.local_signal,
                                                  &mut self.remote_signal,
@@ -459,77 +1055,157 @@ impl Page {
                 }
                 CellType::Dendrite | CellType::Body => {
                     debug!("Signal landed on Dendrite / Body");
+                    if signal > 0 &&
+                       self.cells[index as usize].get_cell_type() == CellType::Body {
+                        self.fired.push(index);
+                    }
 
-                    let target = self.cells[index as usize].get_gate();
+                    let target = N::of(&self.cells[index as usize]);
                     debug!("Signal >= threshold, send to: {:?}", target);
 
                     let sig = Page::process_signal(target,
                                                    &mut self.cells,
                                                    index as usize,
-                                                   x,
-                                                   y,
-                                                   self.offset_x,
-                                                   self.offset_y);
+                                                   position,
+                                                   offset);
 
                     // debug!("Propagated signal: {:?}", sig);
 
                     Page::persist_signal(&mut self.local_signal, &mut self.remote_signal, sig);
-
                 }
                 _ => {}
             };
+
+            Page::<N>::check(&mut self.states,
+                             &mut self.cells[index as usize],
+                             index,
+                             true,
+                             config,
+                             model);
         }
 
         debug!("local_signal: {:?}", self.local_signal);
         debug!("remote_signal: {:?}", self.remote_signal);
     }
 
-    pub fn get_remote_signal(&self) -> &Vec<RemoteSignal> {
-        &self.remote_signal
+    // Lets the model update its state for a cell that was just checked for firing
+    fn check<M: NeuronModel<N>>(states: &mut HashMap<u32, u64>,
+                                cell: &mut Cell,
+                                index: u32,
+                                fired: bool,
+                                config: &CajalConfig,
+                                model: &M) {
+        let mut state = states.get(&index).cloned().unwrap_or(0);
+        model.checked(config, cell, fired, &mut state);
+        if state == 0 {
+            states.remove(&index);
+        } else {
+            states.insert(index, state);
+        }
+    }
+
+    fn tick<M: NeuronModel<N>>(&mut self, config: &CajalConfig, model: &M) {
+        let cells = &mut self.cells;
+        for (index, state) in &mut self.states {
+            model.tick(config, &mut cells[*index as usize], state);
+        }
+        self.states.retain(|_, state| *state != 0);
     }
 
-    fn process_signal(travel_direction: Gate,
-                      cells: &mut Vec<Cell>,
-                      origin: usize,
-                      x: u32,
-                      y: u32,
-                      offset_x: u32,
-                      offset_y: u32)
-                      -> SignalType {
+    // Hands over the signal bound for other pages in exchange for `queue`, which has to
+    // be empty
+    pub fn take_remote_signal(&mut self, queue: &mut Vec<RemoteSignal<N>>) {
+        mem::swap(&mut self.remote_signal, queue);
+    }
 
-        match (travel_direction, x, y) {
-            (Gate::North, _, y) if y < PAGE_WIDTH - 1 => {
-                Page::signal_local(cells, origin, x, y, travel_direction)
-            }
-            (Gate::South, _, y) if y > 0 => {
-                Page::signal_local(cells, origin, x, y, travel_direction)
+    // Signals can't land on an unallocated page, so they don't allocate one.  Leaves the
+    // inbox empty either way
+    pub fn receive_signal(&mut self, inbox: &mut [(usize, Vec<RemoteSignal<N>>)]) {
+        for &mut (_, ref mut signals) in inbox {
+            for s in signals.drain(..) {
+                self.add_signal(s.x % N::page_width(),
+                                s.y % N::page_width(),
+                                s.z % N::page_depth(),
+                                &s);
             }
-            (Gate::East, x, _) if x < PAGE_WIDTH - 1 => {
-                Page::signal_local(cells, origin, x, y, travel_direction)
-            }
-            (Gate::West, x, _) if x > 0 => {
-                Page::signal_local(cells, origin, x, y, travel_direction)
+        }
+    }
+
+    pub fn get_fired(&self) -> Vec<(u32, u32, u32)> {
+        self.fired.iter().map(|i| self.absolute(*i)).collect()
+    }
+
+    pub fn is_firing(&self, x: u32, y: u32, z: u32) -> bool {
+        self.firing.contains(N::to_index(x, y, z))
+    }
+
+    // (axon, receiving cell) in absolute coordinates
+    pub fn get_deliveries(&self) -> Vec<((u32, u32, u32), (u32, u32, u32))> {
+        self.deliveries.iter().map(|&(axon, to)| (axon, self.absolute(to))).collect()
+    }
+
+    // Hands over the Hebbian updates for axons on other pages in exchange for `queue`,
+    // which has to be empty
+    pub fn take_remote_contacts(&mut self, queue: &mut Vec<RemoteContact>) {
+        mem::swap(&mut self.remote_contacts, queue);
+    }
+
+    // Applies the Hebbian updates other pages sent for axons on this one and leaves the
+    // inbox empty
+    pub fn receive_contacts(&mut self,
+                            config: &CajalConfig,
+                            inbox: &mut [(usize, Vec<RemoteContact>)]) {
+        for &mut (_, ref mut contacts) in inbox {
+            for c in contacts.drain(..) {
+                let axon = self.get_mut_cell(c.x % N::page_width(),
+                                             c.y % N::page_width(),
+                                             c.z % N::page_depth());
+                if c.potentiate {
+                    plasticity::potentiate(axon, config.ltp_steps);
+                } else {
+                    plasticity::depress(axon, config.ltd_steps);
+                }
             }
-            (_, _, _) => {
+        }
+    }
+
+    fn absolute(&self, index: u32) -> (u32, u32, u32) {
+        let (x, y, z) = N::to_position(index);
+        (self.offset_x + x, self.offset_y + y, self.offset_z + z)
+    }
+
+    fn process_signal(travel_direction: N,
+                      cells: &mut Vec<Cell>,
+                      origin: usize,
+                      (x, y, z): (u32, u32, u32),
+                      offset: (u32, u32, u32))
+                      -> SignalType<N> {
+
+        match Page::<N>::local_target(x, y, z, travel_direction) {
+            Some(_) => Page::signal_local(cells, origin, x, y, z, travel_direction),
+            None => {
                 let strength = cells[origin].get_strength();
                 let stim = cells[origin].get_stim();
                 let cell_type = cells[origin].get_cell_type();
+                let level = cells[origin].get_pot_level();
+                // The receiving page can't reach back here, so the sender is cleared now
+                // rather than once the signal arrives
+                cells[origin].clear_signal();
                 Page::signal_remote(strength,
                                     stim,
-                                    x,
-                                    y,
-                                    offset_x,
-                                    offset_y,
+                                    (x, y, z),
+                                    offset,
                                     travel_direction,
-                                    cell_type)
+                                    cell_type,
+                                    level)
             }
         }
     }
 
 
-    fn persist_signal(local: &mut Vec<LocalSignal>,
-                      remote: &mut Vec<RemoteSignal>,
-                      change: SignalType) {
+    fn persist_signal(local: &mut Vec<LocalSignal<N>>,
+                      remote: &mut Vec<RemoteSignal<N>>,
+                      change: SignalType<N>) {
         match change {
             SignalType::Local(l) => {
                 local.push(l);
@@ -546,21 +1222,26 @@ impl Page {
                     origin: usize,
                     x: u32,
                     y: u32,
-                    travel_direction: Gate)
-                    -> SignalType {
-        assert!((x > PAGE_WIDTH - 1 && travel_direction == Gate::East) != true);
-        assert!((y > PAGE_WIDTH - 1 && travel_direction == Gate::North) != true);
-
-        let (target, _) = Page::calc_target(x, y, travel_direction);
+                    z: u32,
+                    travel_direction: N)
+                    -> SignalType<N> {
+        let target = match Page::<N>::local_target(x, y, z, travel_direction) {
+            Some(target) => target,
+            None => panic!("signalling off the page from ({},{},{})", x, y, z),
+        };
 
         if cells[target as usize].get_cell_type() != CellType::Empty {
             SignalType::Local(LocalSignal {
                 x: x,
                 y: y,
+                z: z,
                 to_index: target as usize,
                 strength: cells[origin].get_strength(),
                 stim: cells[origin].get_stim(),
                 origin_cell_type: cells[origin].get_cell_type(),
+                travel_direction: travel_direction,
+                level: cells[origin].get_pot_level(),
+                sender: None,
             })
         } else {
             SignalType::NoSignal
@@ -569,134 +1250,160 @@ impl Page {
 
     fn signal_remote(strength: u8,
                      stim: bool,
-                     x: u32,
-                     y: u32,
-                     offset_x: u32,
-                     offset_y: u32,
-                     travel_direction: Gate,
-                     cell_type: CellType)
-                     -> SignalType {
-        debug!("signal_remote: ({},{}) offsets: ({},{}) {:?}",
+                     (x, y, z): (u32, u32, u32),
+                     (offset_x, offset_y, offset_z): (u32, u32, u32),
+                     travel_direction: N,
+                     cell_type: CellType,
+                     level: u8)
+                     -> SignalType<N> {
+        debug!("signal_remote: ({},{},{}) offsets: ({},{},{}) {:?}",
                x,
                y,
+               z,
                offset_x,
                offset_y,
+               offset_z,
                travel_direction);
 
-        if (offset_x == 0 && travel_direction == Gate::West) ||
-           (offset_y == 0 && travel_direction == Gate::South) {
-            return SignalType::NoSignal;
-        }
-
-        let (x, y) = match travel_direction {
-            Gate::North => (offset_x + x, offset_y + y + 1),
-            Gate::South => (offset_x + x, offset_y + y - 1),
-            Gate::West => (offset_x + x - 1, offset_y + y),
-            Gate::East => (offset_x + x + 1, offset_y + y),
-        };
-        debug!("signal-remote: new: ({},{}) {:?}", x, y, travel_direction);
+        let sender = (offset_x + x, offset_y + y, offset_z + z);
+        let (x, y, z) = travel_direction.step(sender.0, sender.1, sender.2);
+        debug!("signal-remote: new: ({},{},{}) {:?}", x, y, z, travel_direction);
 
         SignalType::Remote(RemoteSignal {
             x: x,
             y: y,
+            z: z,
             strength: strength,
             stim: stim,
             origin_cell_type: cell_type,
+            travel_direction: travel_direction,
+            level: level,
+            sender: sender,
         })
     }
 
-    pub fn update_signal(&mut self) -> u32 {
+    // Returns the number of active cells and of signals that reached a cell
+    pub fn update_signal<M: NeuronModel<N>>(&mut self,
+                                            config: &CajalConfig,
+                                            model: &M)
+                                            -> (u32, u32) {
 
         debug!("Stale active cells: {}", self.active.len());
         self.active.clear();
+        self.deliveries.clear();
 
         if self.local_signal.is_empty() {
-            return 0;
+            return (0, 0);
         }
 
+        let mut delivered = 0;
         debug!("Local signals to process: {}", self.local_signal.len());
-        for signal in &self.local_signal {
-
-
-            match (signal.origin_cell_type,
-                   self.cells[signal.to_index].get_cell_type()) {
-                (CellType::Axon, CellType::Axon) => {
-                    let (x, y) = zorder::z_to_xy(signal.to_index as u32);
-                    debug!("({},{}) - ({},{}) == ({},{})",
-                           x,
-                           y,
-                           signal.x,
-                           signal.y,
-                           x as i32 - signal.x as i32,
-                           y as i32 - signal.y as i32);
-                    let (x, y): (i32, i32) = (x as i32 - signal.x as i32,
-                                              y as i32 - signal.y as i32);
-
-
-                    let direction = match (x, y) {
-                        (1, 0) => Gate::West,
-                        (-1, 0) => Gate::East,
-                        (0, 1) => Gate::South,
-                        (0, -1) => Gate::North,
-                        // This condition shouldn't really happen, but if it does (oops), invert
-                        // the gate so it doesn't affect anything
-                        (_, _) => !self.cells[signal.to_index].get_gate(),
-                    };
-
-                    debug!("{:?} == {:?}?",
-                           direction,
-                           self.cells[signal.to_index].get_gate());
-                    if direction == self.cells[signal.to_index].get_gate() {
-                        self.cells[signal.to_index].add_signal(signal.strength);
-                    }
-
-                }
-                (CellType::Axon, CellType::Dendrite) | (CellType::Axon, CellType::Body) => {
-                    if signal.stim == true {
-                        self.cells[signal.to_index].add_signal(signal.strength)
-                    } else {
-                        self.cells[signal.to_index].sub_signal(signal.strength)
-                    }
+        for i in 0..self.local_signal.len() {
+            // Copied out, learning may update the sender
+            let signal = self.local_signal[i];
+            let state = self.states.get(&(signal.to_index as u32)).cloned().unwrap_or(0);
+            let accepts = model.accepts(&self.cells[signal.to_index], state);
+            let target_type = self.cells[signal.to_index].get_cell_type();
+            // Signals from a neighbouring page come in at the receiving cell, their sender
+            // was already cleared on its own page
+            let from_index = N::to_index(signal.x, signal.y, signal.z) as usize;
+
+            if !accepts {
+                debug!("Cell doesn't take up signal this step, dropping it");
+            } else {
+                let mut strength = signal.strength;
+                if config.plasticity != Plasticity::Off &&
+                   signal.origin_cell_type == CellType::Axon &&
+                   (target_type == CellType::Dendrite || target_type == CellType::Body) {
+                    strength = plasticity::scale(signal.level, strength);
+                    self.learn(config, &signal, from_index);
                 }
-                (CellType::Dendrite, CellType::Dendrite) |
-                (CellType::Dendrite, CellType::Body) |
-                (CellType::Body, CellType::Dendrite) |
-                (CellType::Body, CellType::Body) |
-                (CellType::Body, CellType::Axon) => {
-                    self.cells[signal.to_index].add_signal(signal.strength)
-                }
-                (_, _) => {}
+
+                delivered += 1;
+                model.integrate(&mut self.cells[signal.to_index],
+                                &Input {
+                                    origin: signal.origin_cell_type,
+                                    travel_direction: signal.travel_direction,
+                                    strength: strength,
+                                    stim: signal.stim,
+                                });
             }
 
-            let from_index = zorder::xy_to_z(signal.x, signal.y);
-            self.cells[from_index as usize].clear_signal();
-            self.active.insert(signal.to_index as u32);
+            if signal.sender.is_none() {
+                self.cells[from_index].clear_signal();
+            }
+            if accepts {
+                self.active.insert(signal.to_index as u32);
+            }
         }
 
         self.local_signal.clear();
         self.remote_signal.clear();
         debug!("After signaling, {} active cells", self.active.len());
-        self.active.len()
+        (self.active.len(), delivered)
     }
 
-    pub fn add_signal(&mut self, x: u32, y: u32, strength: u8, stim: bool) {
-        let target = zorder::xy_to_z(x, y);
+    // Moves the level of the axon that sent `signal` towards the activity of the cell it
+    // reached with Hebbian plasticity, or notes the delivery for STDP.  Axons on other
+    // pages are updated once their page receives the contact
+    fn learn(&mut self, config: &CajalConfig, signal: &LocalSignal<N>, from_index: usize) {
+        if config.plasticity == Plasticity::Stdp {
+            let sender = signal.sender.unwrap_or_else(|| self.absolute(from_index as u32));
+            self.deliveries.push((sender, signal.to_index as u32));
+            return;
+        }
+        if config.plasticity != Plasticity::Hebbian {
+            return;
+        }
+
+        let cell = self.cells[signal.to_index];
+        let potentiate = match cell.get_signal() {
+            0 => false,
+            s if s >= cell.get_threshold() => true,
+            _ => return,
+        };
+        match signal.sender {
+            Some((x, y, z)) => {
+                self.remote_contacts.push(RemoteContact {
+                    x: x,
+                    y: y,
+                    z: z,
+                    potentiate: potentiate,
+                })
+            }
+            None if potentiate => {
+                plasticity::potentiate(&mut self.cells[from_index], config.ltp_steps)
+            }
+            None => plasticity::depress(&mut self.cells[from_index], config.ltd_steps),
+        }
+    }
 
-        debug!(">>>>>>>> Attempting to add remote signal: ({}, {}) ({}): {} stimulatory? {}",
+    pub fn add_signal(&mut self, x: u32, y: u32, z: u32, signal: &RemoteSignal<N>) {
+        if !self.is_allocated() {
+            return;
+        }
+        let target = N::to_index(x, y, z);
+
+        debug!(">>>>>>>> Attempting to add remote signal: ({}, {}, {}) ({}): {} stimulatory? {}",
                x,
                y,
+               z,
                target,
-               strength,
-               stim);
+               signal.strength,
+               signal.stim);
         if self.cells[target as usize].get_cell_type() != CellType::Empty {
             debug!("Inserting external signal");
             self.local_signal.push(LocalSignal {
                 x: x,
                 y: y,
+                z: z,
                 to_index: target as usize,
-                strength: strength,
-                stim: stim,
-                origin_cell_type: CellType::Axon,
+                strength: signal.strength,
+                stim: signal.stim,
+                origin_cell_type: signal.origin_cell_type,
+                travel_direction: signal.travel_direction,
+                level: signal.level,
+                sender: Some(signal.sender),
             });
         }
     }
@@ -705,28 +1412,274 @@ impl Page {
 
 #[cfg(test)]
 mod test {
-    use super::{Page, Cell, CellType, Gate};
-    use super::ChangeType::Local;
+    use std::io::Cursor;
+    use super::{plasticity, Page, Cell, CellType, Gate, LocalSignal};
+    use super::ChangeType::{Local, NoChange};
+    use grid::Gate3D;
+    use grid::zorder;
+    use config::{CajalConfig, Plasticity};
+    use growth::ChromosomeGrowth;
+    use model::CoDi;
+    use snapshot::Snapshot;
     use test::Bencher;
+    use PAGE_SIZE;
 
     #[test]
     fn page_new() {
-        let _ = Page::new(0.05, 0, 0, &[1, 2, 3, 4]);
+        let _: Page = Page::new(&CajalConfig::default(), 0, 0, 0);
+    }
+
+    // Checksum of the bodies, gates, stim, thresholds and chromosomes the engine placed
+    // before any of them became configurable
+    #[test]
+    fn default_layout() {
+        let config = CajalConfig {
+            density: 0.05,
+            seed: vec![1, 2, 3, 4],
+            ..CajalConfig::default()
+        };
+        let p: Page = Page::new(&config, 0, 0, 0);
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for x in 0..256 {
+            for y in 0..256 {
+                let c = p.get_cell(x, y, 0);
+                let v = (c.get_cell_type() as u32) | (c.get_gate() as u32) << 3 |
+                        (c.get_stim() as u32) << 5 |
+                        (c.get_threshold() as u32) << 6 |
+                        (c.get_chromosome() as u32) << 12;
+                hash = (hash ^ v as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        assert!(hash == 0x804ff23ab07b774f);
     }
 
     #[test]
     fn grow() {
-        let mut p = Page::new(0.05, 0, 0, &[1, 2, 3, 4]);
-        p.grow();
+        let config = CajalConfig::default();
+        let mut p: Page = Page::new(&config, 0, 0, 0);
+        p.grow(&config, &ChromosomeGrowth, 0);
+    }
+
+    #[test]
+    fn grow_3d() {
+        let config = CajalConfig { density: 0.01, ..CajalConfig::default() };
+        let mut p: Page<Gate3D> = Page::new(&config, 0, 0, 0);
+        p.grow(&config, &ChromosomeGrowth, 0);
+        assert!(p.get_change_count() > 0);
+        assert!(p.update()[CellType::Axon as usize] > 0);
+    }
+
+    #[test]
+    fn potentiation() {
+        let config = CajalConfig {
+            plasticity: Plasticity::Hebbian,
+            ltp_steps: 2,
+            ltd_steps: 1,
+            ..CajalConfig::default()
+        };
+        let mut p = body_page(&config, 2);
+        p.get_mut_cell(10, 11, 0).set_cell_type(CellType::Axon);
+        plasticity::reset(p.get_mut_cell(10, 11, 0));
+        let from = zorder::xy_to_z(10, 11) as usize;
+        let mut signal = LocalSignal {
+            x: 10,
+            y: 11,
+            z: 0,
+            to_index: zorder::xy_to_z(10, 10) as usize,
+            strength: 4,
+            stim: true,
+            origin_cell_type: CellType::Axon,
+            travel_direction: Gate::South,
+            level: 0,
+            sender: None,
+        };
+
+        // Silent body at the baseline level, nothing left to depress
+        p.learn(&config, &signal, from);
+        assert!(p.get_cell(10, 11, 0).get_pot_level() == 0);
+
+        p.get_mut_cell(10, 10, 0).set_signal(5);
+        p.learn(&config, &signal, from);
+        assert!(p.get_cell(10, 11, 0).get_pot_level() == 0);
+        assert!(p.get_cell(10, 11, 0).get_ltp_count() == 1);
+        p.learn(&config, &signal, from);
+        assert!(p.get_cell(10, 11, 0).get_pot_level() == 1);
+        assert!(p.get_cell(10, 11, 0).get_ltp_count() == 0);
+
+        // An axon on another page is sent the update instead
+        signal.sender = Some((300, 10, 0));
+        p.learn(&config, &signal, from);
+        assert!(p.get_cell(10, 11, 0).get_ltp_count() == 0);
+        assert!(p.remote_contacts.len() == 1 && p.remote_contacts[0].potentiate);
+
+        signal.sender = None;
+        p.get_mut_cell(10, 10, 0).clear_signal();
+        p.learn(&config, &signal, from);
+        assert!(p.get_cell(10, 11, 0).get_pot_level() == 0);
+
+        // Without plasticity the level never moves
+        let config = CajalConfig::default();
+        p.get_mut_cell(10, 10, 0).set_signal(5);
+        for _ in 0..10 {
+            p.learn(&config, &signal, from);
+        }
+        assert!(p.get_cell(10, 11, 0).get_pot_level() == 0);
+    }
+
+    fn body_page(config: &CajalConfig, threshold: u8) -> Page {
+        let mut p: Page = Page::new(config, 0, 0, 0);
+        p.allocate(config);
+        let cell = p.get_mut_cell(10, 10, 0);
+        cell.set_cell_type(CellType::Body);
+        cell.set_threshold(threshold);
+        p
+    }
+
+    // Signal from a dendrite just north of the body
+    fn deliver(p: &mut Page, strength: u8) {
+        p.local_signal.push(LocalSignal {
+            x: 10,
+            y: 11,
+            z: 0,
+            to_index: zorder::xy_to_z(10, 10) as usize,
+            strength: strength,
+            stim: true,
+            origin_cell_type: CellType::Dendrite,
+            travel_direction: Gate::South,
+            level: 0,
+            sender: None,
+        });
+    }
+
+    #[test]
+    fn leak() {
+        let config = CajalConfig { density: 0.0, leak: 1, ..CajalConfig::default() };
+        let mut p = body_page(&config, 10);
+
+        deliver(&mut p, 4);
+        p.update_signal(&config, &CoDi);
+        assert!(p.get_cell(10, 10, 0).get_signal() == 4);
+        for expected in (0..4).rev() {
+            p.signal(&config, &CoDi);
+            p.update_signal(&config, &CoDi);
+            assert!(p.get_cell(10, 10, 0).get_signal() == expected);
+        }
+        assert!(p.states.is_empty());
+    }
+
+    #[test]
+    fn refractory() {
+        let config = CajalConfig { density: 0.0, refractory: 2, ..CajalConfig::default() };
+        let mut p = body_page(&config, 3);
+
+        p.set_input(10, 10, 0, 5);
+        p.signal(&config, &CoDi);
+        assert!(p.get_cell(10, 10, 0).get_signal() == 0);
+
+        // Input is ignored for two steps after firing
+        for _ in 0..2 {
+            deliver(&mut p, 4);
+            assert!(p.update_signal(&config, &CoDi) == (0, 0));
+            assert!(p.get_cell(10, 10, 0).get_signal() == 0);
+            p.signal(&config, &CoDi);
+        }
+
+        deliver(&mut p, 2);
+        assert!(p.update_signal(&config, &CoDi) == (1, 1));
+        assert!(p.get_cell(10, 10, 0).get_signal() == 2);
+    }
+
+    #[test]
+    fn lazy_allocation() {
+        let config = CajalConfig { density: 0.0, ..CajalConfig::default() };
+        let mut p: Page = Page::new(&config, 256, 0, 0);
+        assert!(!p.is_allocated());
+        assert!(p.get_cell(10, 10, 0).get_cell_type() == CellType::Empty);
+        p.grow(&config, &ChromosomeGrowth, 0);
+        p.update();
+
+        p.allocate(&config);
+        assert!(p.is_allocated());
+
+        let mut q: Page = Page::new(&config, 256, 0, 0);
+        q.allocate(&config);
+        for i in 0..256 {
+            assert!(p.get_cell(i, i, 0).raw() == q.get_cell(i, i, 0).raw());
+        }
+    }
+
+    #[test]
+    fn load_checks_indices() {
+        let config = CajalConfig::default();
+        let mut p: Page = Page::new(&config, 0, 0, 0);
+        let mut buf = Vec::new();
+        p.save(&mut buf).unwrap();
+        assert!(Page::<Gate>::load(&mut Cursor::new(buf)).is_ok());
+        let mut buf = Vec::new();
+        p.save(&mut buf).unwrap();
+        assert!(Page::<Gate3D>::load(&mut Cursor::new(buf)).is_err());
+
+        p.local_signal.push(LocalSignal {
+            x: 0,
+            y: 0,
+            z: 0,
+            to_index: PAGE_SIZE as usize,
+            strength: 1,
+            stim: true,
+            origin_cell_type: CellType::Axon,
+            travel_direction: Gate::North,
+            level: 0,
+            sender: None,
+        });
+        let mut buf = Vec::new();
+        p.save(&mut buf).unwrap();
+        assert!(Page::<Gate>::load(&mut Cursor::new(buf)).is_err());
+
+        p.local_signal.clear();
+        p.cells[0] = Cell::from_raw(7);
+        let mut buf = Vec::new();
+        p.save(&mut buf).unwrap();
+        assert!(Page::<Gate>::load(&mut Cursor::new(buf)).is_err());
+    }
+
+    #[test]
+    fn state_hash_covers_queues() {
+        let config = CajalConfig::default();
+        let p: Page = Page::new(&config, 0, 0, 0);
+        let hash = p.state_hash(0);
+
+        let mut q = p.clone();
+        q.states.insert(10, 1);
+        assert!(q.state_hash(0) != hash);
+
+        let mut q = p.clone();
+        q.changes.insert(10, Cell::from_raw(0));
+        assert!(q.state_hash(0) != hash);
+
+        let mut q = p.clone();
+        q.local_signal.push(LocalSignal {
+            x: 0,
+            y: 0,
+            z: 0,
+            to_index: 1,
+            strength: 1,
+            stim: true,
+            origin_cell_type: CellType::Axon,
+            travel_direction: Gate::North,
+            level: 0,
+            sender: None,
+        });
+        assert!(q.state_hash(0) != hash);
     }
 
     #[test]
     fn create_change() {
-        let change = Page::create_change(CellType::Axon, Gate::North, true);
+        let change = Page::<Gate>::create_change(CellType::Axon, Gate::North, true, 7);
         assert!(change.get_cell_type() == CellType::Axon);
         assert!(change.get_gate() == Gate::North);
+        assert!(change.get_strength() == 7);
 
-        let change = Page::create_change(CellType::Dendrite, Gate::West, true);
+        let change = Page::<Gate>::create_change(CellType::Dendrite, Gate::West, true, 7);
         assert!(change.get_cell_type() == CellType::Dendrite);
         assert!(change.get_gate() == Gate::West);
     }
@@ -739,7 +1692,7 @@ mod test {
         assert!(data[1].get_cell_type() == CellType::Empty);
         assert!(data[1].get_gate() == Gate::North);
 
-        let change = Page::grow_local(&mut data, 0, 0, CellType::Axon, Gate::North, true);
+        let change = Page::grow_local(&mut data, 0, 0, 0, CellType::Axon, Gate::North, true, 7);
         assert!(data[0].get_cell_type() == CellType::Empty);
         assert!(data[0].get_gate() == Gate::North);
         assert!(data[1].get_cell_type() == CellType::Empty);
@@ -755,7 +1708,14 @@ mod test {
             _ => assert!(1 == 2),
         }
 
-        let change = Page::grow_local(&mut data, 1, 0, CellType::Dendrite, Gate::West, true);
+        let change = Page::grow_local(&mut data,
+                                      1,
+                                      0,
+                                      0,
+                                      CellType::Dendrite,
+                                      Gate::West,
+                                      true,
+                                      7);
         assert!(data[0].get_cell_type() == CellType::Empty);
         assert!(data[0].get_gate() == Gate::North);
         assert!(data[1].get_cell_type() == CellType::Empty);
@@ -778,21 +1738,48 @@ mod test {
     #[should_panic]
     fn grow_local_bad_north() {
         let mut data = vec![Cell::new(), Cell::new()];
-        let _ = Page::grow_local(&mut data, 0, 63, CellType::Axon, Gate::North, true);
+        let _ = Page::grow_local(&mut data, 0, 63, 0, CellType::Axon, Gate::North, true, 7);
     }
 
     #[test]
     #[should_panic]
     fn grow_local_bad_east() {
         let mut data = vec![Cell::new(), Cell::new()];
-        let _ = Page::grow_local(&mut data, 63, 0, CellType::Axon, Gate::East, true);
+        let _ = Page::grow_local(&mut data, 63, 0, 0, CellType::Axon, Gate::East, true, 7);
+    }
+
+    #[test]
+    fn grow_local_3d() {
+        let mut data = vec![Cell::new(); 8];
+        match Page::grow_local(&mut data, 0, 0, 0, CellType::Axon, Gate3D::Up, true, 7) {
+            Local((target, change)) => {
+                assert!(target == zorder::xyz_to_z(0, 0, 1));
+                assert!(change.get_gate_3d() == Gate3D::Down);
+                assert!(change.get_strength() == 7);
+            }
+            _ => panic!("expected a local change"),
+        }
+
+        data[zorder::xyz_to_z(1, 0, 0) as usize].set_cell_type(CellType::Dendrite);
+        match Page::grow_local(&mut data, 0, 0, 0, CellType::Axon, Gate3D::East, true, 7) {
+            NoChange => {}
+            _ => panic!("grew into an occupied cell"),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn grow_local_bad_down() {
+        let mut data = vec![Cell::new(); 8];
+        let _ = Page::grow_local(&mut data, 0, 0, 0, CellType::Axon, Gate3D::Down, true, 7);
     }
 
 
     #[bench]
     fn bench_grow(b: &mut Bencher) {
-        let mut page = Page::new(0.05, 0, 0, &[1, 2, 3, 4]);
-        b.iter(|| page.grow());
+        let config = CajalConfig::default();
+        let mut page: Page = Page::new(&config, 0, 0, 0);
+        b.iter(|| page.grow(&config, &ChromosomeGrowth, 0));
     }
 
 }
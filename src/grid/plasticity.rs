@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use super::{Cell, CellType, Grid, Neighbourhood};
+use growth::GrowthRule;
+use memory;
+use model::NeuronModel;
+use snapshot::{self, Snapshot};
+
+// An axon keeps the potentiation of its contacts with dendrites and bodies in the POT bits
+// of the cell, see `Cell::get_pot_level`.  Every level adds half of the strength
+pub fn scale(level: u8, strength: u8) -> u8 {
+    strength + strength * level / 2
+}
+
+// Counts an event towards raising the level of the axon, `steps` events raise it by one.
+// 0 disables potentiation
+pub fn potentiate(axon: &mut Cell, steps: u8) {
+    if steps == 0 {
+        return;
+    }
+
+    let count = axon.get_ltp_count() + 1;
+    if count >= steps {
+        let level = axon.get_pot_level() + 1;
+        axon.set_pot_level(level);
+        axon.set_ltp_count(0);
+        axon.set_ltd_count(0);
+    } else {
+        axon.set_ltp_count(count);
+    }
+}
+
+pub fn depress(axon: &mut Cell, steps: u8) {
+    if steps == 0 {
+        return;
+    }
+
+    let count = axon.get_ltd_count() + 1;
+    if count >= steps {
+        let level = axon.get_pot_level().saturating_sub(1);
+        axon.set_pot_level(level);
+        axon.set_ltp_count(0);
+        axon.set_ltd_count(0);
+    } else {
+        axon.set_ltd_count(count);
+    }
+}
+
+pub fn reset(axon: &mut Cell) {
+    axon.set_pot_level(0);
+    axon.set_ltp_count(0);
+    axon.set_ltd_count(0);
+}
+
+
+// Spike timing state.  Only events younger than the window are kept, anything older
+// can't be paired with a new event anymore
+#[derive(Clone, Default)]
+pub struct Stdp {
+    // Body -> step it last crossed threshold
+    last_fire: HashMap<(u32, u32, u32), u64>,
+    // Body -> (axon, step) for every recent delivery from an axon into its dendrite tree
+    recent: HashMap<(u32, u32, u32), Vec<((u32, u32, u32), u64)>>,
+    // Receiving cell -> body at the root of its dendrite tree, as of growth step
+    // `generation`
+    owners: HashMap<(u32, u32, u32), Option<(u32, u32, u32)>>,
+    generation: u64,
+}
+
+impl Stdp {
+    pub fn memory_usage(&self) -> usize {
+        memory::map_bytes(&self.last_fire) + memory::map_bytes(&self.recent) +
+        self.recent.values().map(memory::vec_bytes).fold(0, |acc, x| acc + x) +
+        memory::map_bytes(&self.owners)
+    }
+
+    // Whether every cell referred to lies within a grid of the given size
+    pub fn within(&self, width: u32, height: u32, depth: u32) -> bool {
+        let inside = |&(x, y, z): &(u32, u32, u32)| x < width && y < height && z < depth;
+        self.last_fire.keys().all(&inside) &&
+        self.recent.iter().all(|(body, axons)| {
+            inside(body) && axons.iter().all(|&(axon, _)| inside(&axon))
+        })
+    }
+
+    // Drops everything outside of the window
+    fn forget(&mut self, step: u64, window: u64) {
+        for axons in self.recent.values_mut() {
+            axons.retain(|&(_, s)| step - s <= window);
+        }
+        self.recent.retain(|_, axons| !axons.is_empty());
+        self.last_fire.retain(|_, s| step - *s <= window);
+    }
+}
+
+impl Snapshot for Stdp {
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut last_fire: Vec<(&(u32, u32, u32), &u64)> = self.last_fire.iter().collect();
+        last_fire.sort();
+        try!(snapshot::write_u32(writer, last_fire.len() as u32));
+        for (&body, step) in last_fire {
+            try!(snapshot::write_position(writer, body));
+            try!(snapshot::write_u64(writer, *step));
+        }
+
+        let mut recent = Vec::new();
+        for (&body, axons) in &self.recent {
+            recent.extend(axons.iter().map(|&(axon, step)| (body, step, axon)));
+        }
+        recent.sort();
+        try!(snapshot::write_u32(writer, recent.len() as u32));
+        for (body, step, axon) in recent {
+            try!(snapshot::write_position(writer, axon));
+            try!(snapshot::write_position(writer, body));
+            try!(snapshot::write_u64(writer, step));
+        }
+        Ok(())
+    }
+
+    fn load<R: Read>(reader: &mut R) -> io::Result<Stdp> {
+        let mut stdp = Stdp::default();
+
+        let len = try!(snapshot::read_u32(reader));
+        for _ in 0..len {
+            let body = try!(snapshot::read_position(reader));
+            stdp.last_fire.insert(body, try!(snapshot::read_u64(reader)));
+        }
+
+        let len = try!(snapshot::read_u32(reader));
+        for _ in 0..len {
+            let axon = try!(snapshot::read_position(reader));
+            let body = try!(snapshot::read_position(reader));
+            let step = try!(snapshot::read_u64(reader));
+            stdp.recent.entry(body).or_insert_with(Vec::new).push((axon, step));
+        }
+        Ok(stdp)
+    }
+}
+
+impl<M: NeuronModel<N>, G: GrowthRule<N>, N: Neighbourhood> Grid<M, G, N> {
+    // Pairs this step's threshold crossings with recent deliveries into the same neuron.
+    // A delivery followed by a crossing potentiates the axon, a crossing followed by a
+    // delivery depresses it.  Deliveries are (axon, receiving cell)
+    pub fn apply_stdp(&mut self,
+                      fired: &[(u32, u32, u32)],
+                      deliveries: &[((u32, u32, u32), (u32, u32, u32))]) {
+        let step = self.step;
+        self.stdp.forget(step, self.config.stdp_window as u64);
+        if self.stdp.generation != self.generation {
+            self.stdp.owners.clear();
+            self.stdp.generation = self.generation;
+        }
+
+        let mut ltp = Vec::new();
+        for body in fired {
+            if let Some(axons) = self.stdp.recent.get(body) {
+                ltp.extend(axons.iter().filter(|&&(_, s)| s < step).map(|&(axon, _)| axon));
+            }
+            self.stdp.last_fire.insert(*body, step);
+        }
+
+        // A delivery in the same step as the crossing may have caused it, so only
+        // crossings from earlier steps count as post before pre
+        let mut ltd = Vec::new();
+        for &(axon, target) in deliveries {
+            let owner = match self.owner(target) {
+                Some(owner) => owner,
+                None => continue,
+            };
+            match self.stdp.last_fire.get(&owner) {
+                Some(&s) if s < step => ltd.push(axon),
+                _ => {}
+            }
+            self.stdp.recent.entry(owner).or_insert_with(Vec::new).push((axon, step));
+        }
+
+        debug!("STDP: {} potentiated, {} depressed", ltp.len(), ltd.len());
+        let (ltp_steps, ltd_steps) = (self.config.ltp_steps, self.config.ltd_steps);
+        for (x, y, z) in ltp {
+            potentiate(self.cell_at_mut(x, y, z), ltp_steps);
+        }
+        for (x, y, z) in ltd {
+            depress(self.cell_at_mut(x, y, z), ltd_steps);
+        }
+    }
+
+    // Follows the gates of a dendrite back to its body
+    fn owner(&mut self, position: (u32, u32, u32)) -> Option<(u32, u32, u32)> {
+        if let Some(owner) = self.stdp.owners.get(&position) {
+            return *owner;
+        }
+
+        let mut owner = None;
+        let mut current = position;
+        let cells = self.width as u64 * self.height as u64 * self.depth as u64;
+        for _ in 0..cells {
+            let cell = *self.cell_at(current.0, current.1, current.2);
+            match cell.get_cell_type() {
+                CellType::Body => {
+                    owner = Some(current);
+                    break;
+                }
+                CellType::Dendrite => {
+                    match self.neighbour_at(current.0, current.1, current.2, N::of(&cell)) {
+                        Some(parent) => current = parent,
+                        None => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        self.stdp.owners.insert(position, owner);
+        owner
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{depress, potentiate, reset, scale};
+    use grid::{Cell, CellType, Gate, Grid};
+    use config::{CajalBuilder, CajalConfig, Plasticity};
+
+    #[test]
+    fn counters() {
+        let mut axon = Cell::new();
+        potentiate(&mut axon, 2);
+        assert!(axon.get_pot_level() == 0 && axon.get_ltp_count() == 1);
+        potentiate(&mut axon, 2);
+        assert!(axon.get_pot_level() == 1 && axon.get_ltp_count() == 0);
+        assert!(scale(axon.get_pot_level(), 4) == 6);
+
+        depress(&mut axon, 0);
+        assert!(axon.get_pot_level() == 1);
+        depress(&mut axon, 1);
+        depress(&mut axon, 1);
+        assert!(axon.get_pot_level() == 0);
+
+        axon.set_pot_level(3);
+        potentiate(&mut axon, 1);
+        assert!(axon.get_pot_level() == 3 && axon.get_ltp_count() == 0);
+    }
+
+    #[test]
+    fn growth_resets_potentiation() {
+        let mut cajal = CajalBuilder::new().plasticity(Plasticity::Hebbian).build().unwrap();
+        cajal.grow();
+
+        let mut axons = 0;
+        for x in 0..cajal.width() {
+            for y in 0..cajal.height() {
+                let cell = cajal.get_cell(x, y);
+                if cell.get_cell_type() == CellType::Axon {
+                    assert!(cell.get_pot_level() == 0 && cell.get_ltp_count() == 0 &&
+                            cell.get_ltd_count() == 0);
+                    axons += 1;
+                }
+            }
+        }
+        assert!(axons > 0);
+    }
+
+    #[test]
+    fn hebbian_from_builder() {
+        let mut cajal = CajalBuilder::new()
+                            .dimensions(1, 1)
+                            .density(0.0)
+                            .plasticity(Plasticity::Hebbian)
+                            .build()
+                            .unwrap();
+        {
+            let grid = &mut cajal.grid;
+            let axon = grid.get_mut_cell(10, 10);
+            axon.set_cell_type(CellType::Axon);
+            axon.set_gate(Gate::West);
+            axon.set_strength(5);
+            axon.set_stim(true);
+            reset(axon);
+
+            // Above threshold while the axon delivers, so the two are co-active
+            let dendrite = grid.get_mut_cell(11, 10);
+            dendrite.set_cell_type(CellType::Dendrite);
+            dendrite.set_gate(Gate::East);
+            dendrite.set_threshold(10);
+            dendrite.set_signal(20);
+        }
+
+        for _ in 0..4 {
+            cajal.set_input(10, 10, 63);
+            cajal.signal_step();
+            cajal.grid.get_mut_cell(11, 10).set_signal(20);
+        }
+        assert!(cajal.grid.get_cell(10, 10).get_pot_level() > 0);
+    }
+
+    #[test]
+    fn stdp_pairs_by_timing() {
+        let mut grid = Grid::new(&CajalConfig {
+            size: 1,
+            density: 0.0,
+            plasticity: Plasticity::Stdp,
+            ltp_steps: 1,
+            ltd_steps: 1,
+            ..CajalConfig::default()
+        });
+
+        // Body at (10, 10) with a dendrite to its east, which the axon at (13, 10)
+        // signals into
+        grid.get_mut_cell(10, 10).set_cell_type(CellType::Body);
+        for x in 11..13 {
+            let cell = grid.get_mut_cell(x, 10);
+            cell.set_cell_type(CellType::Dendrite);
+            cell.set_gate(Gate::West);
+        }
+        grid.get_mut_cell(13, 10).set_cell_type(CellType::Axon);
+        reset(grid.get_mut_cell(13, 10));
+        let delivery = ((13, 10, 0), (12, 10, 0));
+
+        // Pre before post
+        grid.apply_stdp(&[], &[delivery]);
+        grid.step += 1;
+        grid.apply_stdp(&[(10, 10, 0)], &[]);
+        assert!(grid.get_cell(13, 10).get_pot_level() == 1);
+
+        // Post before pre
+        grid.step += 1;
+        grid.apply_stdp(&[], &[delivery]);
+        assert!(grid.get_cell(13, 10).get_pot_level() == 0);
+
+        // Outside of the window nothing pairs up
+        grid.step += 100;
+        grid.apply_stdp(&[(10, 10, 0)], &[]);
+        assert!(grid.get_cell(13, 10).get_pot_level() == 0);
+
+        // A delivery in the step of the crossing isn't depressed
+        grid.get_mut_cell(13, 10).set_pot_level(1);
+        grid.step += 1;
+        grid.apply_stdp(&[(10, 10, 0)], &[delivery]);
+        assert!(grid.get_cell(13, 10).get_pot_level() == 1);
+        grid.step += 1;
+        grid.apply_stdp(&[(10, 10, 0)], &[]);
+        assert!(grid.get_cell(13, 10).get_pot_level() == 2);
+    }
+
+    #[test]
+    fn stdp_follows_growth() {
+        let mut grid = Grid::new(&CajalConfig {
+            size: 1,
+            density: 0.0,
+            plasticity: Plasticity::Stdp,
+            ltp_steps: 1,
+            ltd_steps: 1,
+            ..CajalConfig::default()
+        });
+
+        // A dendrite at (11, 10) between bodies to its west and east, pointing west first
+        grid.get_mut_cell(10, 10).set_cell_type(CellType::Body);
+        grid.get_mut_cell(12, 10).set_cell_type(CellType::Body);
+        grid.get_mut_cell(11, 10).set_cell_type(CellType::Dendrite);
+        grid.get_mut_cell(11, 10).set_gate(Gate::West);
+        grid.get_mut_cell(11, 11).set_cell_type(CellType::Axon);
+        reset(grid.get_mut_cell(11, 11));
+        let delivery = ((11, 11, 0), (11, 10, 0));
+
+        grid.apply_stdp(&[], &[delivery]);
+        grid.step += 1;
+        grid.apply_stdp(&[(10, 10, 0)], &[]);
+        assert!(grid.get_cell(11, 11).get_pot_level() == 1);
+
+        // Once the grid has grown the dendrite is looked up again
+        grid.get_mut_cell(11, 10).set_gate(Gate::East);
+        grid.generation += 1;
+        grid.step += 1;
+        grid.apply_stdp(&[], &[delivery]);
+        grid.step += 1;
+        grid.apply_stdp(&[(12, 10, 0)], &[]);
+        assert!(grid.get_cell(11, 11).get_pot_level() == 2);
+    }
+}
@@ -27,6 +27,33 @@ fn compact_by_2(z: u32) -> u32 {
     x
 }
 
+// 3D codes interleave 10 bits per axis, which leaves room for pages up to 1024 wide
+pub fn xyz_to_z(x: u32, y: u32, z: u32) -> u32 {
+    (split_by_3(z) << 2) + (split_by_3(y) << 1) + split_by_3(x)
+}
+
+fn split_by_3(x: u32) -> u32 {
+    let mut x = x & 0x000003ff;
+    x = (x ^ (x << 16)) & 0xff0000ff;
+    x = (x ^ (x << 8)) & 0x0300f00f;
+    x = (x ^ (x << 4)) & 0x030c30c3;
+    x = (x ^ (x << 2)) & 0x09249249;
+    x
+}
+
+pub fn z_to_xyz(z: u32) -> (u32, u32, u32) {
+    (compact_by_3(z), compact_by_3(z >> 1), compact_by_3(z >> 2))
+}
+
+fn compact_by_3(z: u32) -> u32 {
+    let mut x = z & 0x09249249;
+    x = (x ^ (x >> 2)) & 0x030c30c3;
+    x = (x ^ (x >> 4)) & 0x0300f00f;
+    x = (x ^ (x >> 8)) & 0xff0000ff;
+    x = (x ^ (x >> 16)) & 0x000003ff;
+    x
+}
+
 #[cfg(test)]
 mod test {
 
@@ -91,5 +118,29 @@ mod test {
         assert!(z == 15);
     }
 
+    #[test]
+    fn simple_conversion_3d() {
+        for x in 0..40 {
+            for y in 0..40 {
+                for z in 0..40 {
+                    let code = xyz_to_z(x, y, z);
+                    assert!(z_to_xyz(code) == (x, y, z));
+                }
+            }
+        }
+        assert!(z_to_xyz(xyz_to_z(1023, 1023, 1023)) == (1023, 1023, 1023));
+    }
 
+    #[test]
+    fn z_pattern_3d() {
+        assert!(xyz_to_z(0, 0, 0) == 0);
+        assert!(xyz_to_z(1, 0, 0) == 1);
+        assert!(xyz_to_z(0, 1, 0) == 2);
+        assert!(xyz_to_z(1, 1, 0) == 3);
+        assert!(xyz_to_z(0, 0, 1) == 4);
+        assert!(xyz_to_z(1, 1, 1) == 7);
+        assert!(xyz_to_z(2, 0, 0) == 8);
+        assert!(xyz_to_z(0, 0, 2) == 32);
+        assert!(xyz_to_z(31, 31, 31) == 32767);
+    }
 }
@@ -0,0 +1,78 @@
+use rand::StdRng;
+
+use grid::{Cell, CellType, Gate, Neighbourhood};
+
+// Decides for every active cell and direction whether something grows there, and what.
+// `neighbour` is the cell in that direction, or None when it lies on another page.  Growth
+// only ever fills empty cells, whatever the rule returns.  Pages grow in parallel, each
+// with its own rng that is reseeded every generation
+pub trait GrowthRule<N = Gate>: Send + Sync {
+    fn grow(&self,
+            cell: &Cell,
+            direction: N,
+            neighbour: Option<&Cell>,
+            rng: &mut StdRng)
+            -> Option<CellType>;
+}
+
+// The CoDi rule: a cell grows more of itself into every direction its chromosome allows
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ChromosomeGrowth;
+
+impl<N: Neighbourhood> GrowthRule<N> for ChromosomeGrowth {
+    fn grow(&self,
+            cell: &Cell,
+            direction: N,
+            _: Option<&Cell>,
+            _: &mut StdRng)
+            -> Option<CellType> {
+        if direction.in_chromosome(cell) {
+            Some(cell.get_cell_type())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, StdRng};
+    use super::{ChromosomeGrowth, GrowthRule};
+    use config::CajalConfig;
+    use grid::{Cell, CellType, Gate};
+    use model::CoDi;
+    use Cajal;
+
+    // Grows like the chromosome says, but only every other time
+    struct Sometimes;
+
+    impl GrowthRule for Sometimes {
+        fn grow(&self,
+                cell: &Cell,
+                direction: Gate,
+                neighbour: Option<&Cell>,
+                rng: &mut StdRng)
+                -> Option<CellType> {
+            match rng.gen() {
+                true => ChromosomeGrowth.grow(cell, direction, neighbour, rng),
+                false => None,
+            }
+        }
+    }
+
+    #[test]
+    fn custom_growth() {
+        let config = CajalConfig { size: 1, density: 0.01, ..CajalConfig::default() };
+        let mut a = Cajal::with_rules(&config, CoDi, Sometimes);
+        let mut b = Cajal::with_rules(&config, CoDi, Sometimes);
+        let mut full = Cajal::with_config(&config);
+
+        let first = a.grow_step().grown();
+        assert!(b.grow_step().grown() == first);
+        assert!(full.grow_step().grown() > first);
+
+        a.grow();
+        b.grow();
+        assert!(a.state_hash() == b.state_hash());
+    }
+}
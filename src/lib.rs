@@ -13,80 +13,608 @@ extern crate num;
 extern crate roaring;
 extern crate rayon;
 extern crate rand;
+extern crate rustc_serialize;
+extern crate toml;
 
-pub use grid::{Cell, CellType};
+pub use grid::{Cell, CellType, Chromosome, Chromosome3D, Gate, Gate3D, Neighbourhood};
+pub use connectome::Connectome;
+pub use config::{Boundary, CajalBuilder, CajalConfig, ConfigError, Plasticity};
+pub use encode::{Encoder, Schedule};
+pub use memory::{MemoryReport, MemoryUsage};
+pub use error::CajalError;
+pub use evolve::{Evolution, Genome};
+pub use growth::{ChromosomeGrowth, GrowthRule};
+pub use model::{CoDi, Input, NeuronModel};
+pub use output::{Readout, Region};
+pub use raster::Raster;
+pub use report::StepReport;
+pub use record::{Recorder, Replayer, ReplayError};
+pub use task::Task;
 use grid::Grid;
+use encode::InputRegion;
+use output::Output;
+use std::io::{self, Read, Write};
 
  mod grid;
+mod snapshot;
+mod error;
+mod memory;
+pub mod config;
+pub mod connectome;
+pub mod encode;
+pub mod evolve;
+pub mod growth;
+pub mod model;
+pub mod output;
+pub mod raster;
+pub mod record;
+pub mod render;
+pub mod report;
+pub mod task;
 
 pub const PAGE_SIZE: u32 = 65536;
 pub const PAGE_WIDTH: u32 = 256;
 
-trait ReportMemory {
-    fn memory(&self) -> u32;
-}
-
 
-pub struct Cajal {
-    grid: Grid,
+#[derive(Clone)]
+pub struct Cajal<M = CoDi, G = ChromosomeGrowth> {
+    grid: Grid<M, G>,
+    inputs: Vec<InputRegion>,
+    outputs: Vec<Output>,
+    raster: Option<Raster>,
 }
 
 impl Default for Cajal {
     fn default() -> Cajal {
-        Cajal { grid: Grid::default() }
+        Cajal {
+            grid: Grid::default(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            raster: None,
+        }
     }
 }
 
-impl ReportMemory for Cajal {
-    fn memory(&self) -> u32 {
-        self.grid.memory()
+impl Cajal {
+    // Panics if the parameters are invalid, see `try_new`
+    pub fn new(size: u32, density: f32, seed: &[usize]) -> Cajal {
+        match Cajal::try_new(size, density, seed) {
+            Ok(cajal) => cajal,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_new(size: u32, density: f32, seed: &[usize]) -> Result<Cajal, CajalError> {
+        Cajal::try_with_config(&CajalConfig {
+            size: size,
+            density: density,
+            seed: seed.to_vec(),
+            ..CajalConfig::default()
+        })
+    }
+
+    pub fn with_config(config: &CajalConfig) -> Cajal {
+        match Cajal::try_with_config(config) {
+            Ok(cajal) => cajal,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_with_config(config: &CajalConfig) -> Result<Cajal, CajalError> {
+        Cajal::try_with_model(config, CoDi)
+    }
+
+    pub fn try_from_genome(config: &CajalConfig, genome: &Genome) -> Result<Cajal, CajalError> {
+        Cajal::try_from_genome_with_rules(config, genome, CoDi, ChromosomeGrowth)
+    }
+
+    // Input and output regions and spike rasters are not part of a snapshot
+    pub fn load<R: Read>(reader: &mut R) -> io::Result<Cajal> {
+        Cajal::load_with_rules(reader, CoDi, ChromosomeGrowth)
+    }
+
+    pub fn connectome(&self) -> Connectome {
+        Connectome::extract(self)
+    }
+
+    fn check_config(config: &CajalConfig) -> Result<(), CajalError> {
+        check_config::<Gate>(config)
     }
 }
 
-impl Cajal {
-    pub fn new(size: u32, density: f32, seed: &[usize]) -> Cajal {
-        Cajal { grid: Grid::new(size, density, seed) }
+impl<M: NeuronModel> Cajal<M> {
+    // Panics if the config is invalid, see `try_with_model`
+    pub fn with_model(config: &CajalConfig, model: M) -> Cajal<M> {
+        match Cajal::try_with_model(config, model) {
+            Ok(cajal) => cajal,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_with_model(config: &CajalConfig, model: M) -> Result<Cajal<M>, CajalError> {
+        Cajal::try_with_rules(config, model, ChromosomeGrowth)
+    }
+}
+
+impl<M: NeuronModel, G: GrowthRule> Cajal<M, G> {
+    // Panics if the config is invalid, see `try_with_rules`
+    pub fn with_rules(config: &CajalConfig, model: M, growth: G) -> Cajal<M, G> {
+        match Cajal::try_with_rules(config, model, growth) {
+            Ok(cajal) => cajal,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_with_rules(config: &CajalConfig,
+                          model: M,
+                          growth: G)
+                          -> Result<Cajal<M, G>, CajalError> {
+        try!(Cajal::check_config(config));
+        Ok(Cajal {
+            grid: Grid::with_rules(config, model, growth),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            raster: None,
+        })
+    }
+
+    // Builds a grid whose cells carry the genes of `genome` instead of drawing their own
+    pub fn try_from_genome_with_rules(config: &CajalConfig,
+                                      genome: &Genome,
+                                      model: M,
+                                      growth: G)
+                                      -> Result<Cajal<M, G>, CajalError> {
+        try!(Cajal::check_config(config));
+        if genome.width() != config.pages_x() * PAGE_WIDTH ||
+           genome.height() != config.pages_y() * PAGE_WIDTH {
+            return Err(CajalError::Config(ConfigError::Invalid(format!("a genome of {}x{} \
+                                                                        cells does not fit \
+                                                                        the grid",
+                                                                       genome.width(),
+                                                                       genome.height()))));
+        }
+
+        Ok(Cajal {
+            grid: Grid::with_genome(config, genome, model, growth),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            raster: None,
+        })
     }
 
     pub fn grow(&mut self) {
         self.grid.grow();
     }
 
-    pub fn grow_step(&mut self) -> u32 {
+    pub fn grow_step(&mut self) -> StepReport {
         self.grid.grow_step()
     }
 
+    // Side length of a square grid, same as `width()`
+    pub fn dimension(&self) -> u32 {
+        self.grid.width()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.grid.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.grid.height()
+    }
+
     pub fn get_cell(&self, x: u32, y: u32) -> &Cell {
         self.grid.get_cell(x, y)
     }
 
+    pub fn try_get_cell(&self, x: u32, y: u32) -> Result<&Cell, CajalError> {
+        try!(self.check_bounds(x, y));
+        Ok(self.grid.get_cell(x, y))
+    }
+
+    // Whether the cell passed signal on during the last signal step
+    pub fn is_firing(&self, x: u32, y: u32) -> bool {
+        self.grid.is_firing(x, y)
+    }
+
+    // Also keeps going until every queued input has been applied
     pub fn signal(&mut self) {
-        self.grid.signal();
+        loop {
+            let report = self.signal_step();
+
+            if self.is_settled(&report) {
+                break;
+            }
+        }
     }
 
-    pub fn signal_step(&mut self) -> u32 {
-        self.grid.signal_step()
+    // Nothing is active and no encoded input is left to apply
+    fn is_settled(&self, report: &StepReport) -> bool {
+        report.active_cells == 0 && self.inputs.iter().all(|input| input.pending() == 0)
+    }
+
+    pub fn signal_step(&mut self) -> StepReport {
+        for input in &mut self.inputs {
+            for (x, y, sig) in input.next() {
+                self.grid.set_input(x, y, sig);
+            }
+        }
+
+        let report = self.grid.signal_step();
+        if let Some(ref mut raster) = self.raster {
+            raster.push(&self.grid.get_fired());
+        }
+        let grid = &self.grid;
+        for output in &mut self.outputs {
+            output.read(grid);
+        }
+        report
     }
 
     pub fn set_input(&mut self, x: u32, y: u32, sig: u8) {
         self.grid.set_input(x, y, sig);
     }
+
+    // Panics if the grid has too many cells for a raster, see `try_start_raster`
+    pub fn start_raster(&mut self) {
+        if let Err(e) = self.try_start_raster() {
+            panic!("{}", e);
+        }
+    }
+
+    // Records the bodies that fire during every signal step from now on, replacing any
+    // raster being recorded
+    pub fn try_start_raster(&mut self) -> Result<(), CajalError> {
+        self.raster = Some(try!(Raster::new(self.width(), self.height())));
+        Ok(())
+    }
+
+    pub fn raster(&self) -> Option<&Raster> {
+        self.raster.as_ref()
+    }
+
+    pub fn stop_raster(&mut self) -> Option<Raster> {
+        self.raster.take()
+    }
+
+    // Partly estimated, see `MemoryUsage`
+    pub fn memory_usage(&self) -> MemoryReport {
+        let mut report = self.grid.memory_usage();
+        report.raster = self.raster.as_ref().map_or(0, |raster| raster.memory_usage());
+        report
+    }
+
+    // Registers a region encoders can feed, replacing any region of the same name.  Every
+    // cell has to lie within the grid
+    pub fn add_input(&mut self, name: &str, region: Region) -> Result<(), CajalError> {
+        try!(region.check(self.width(), self.height()));
+        self.inputs.retain(|i| i.name() != name);
+        self.inputs.push(InputRegion::new(name, region));
+        Ok(())
+    }
+
+    pub fn inputs(&self) -> &[InputRegion] {
+        &self.inputs
+    }
+
+    // Queues `values` on the named input region, to be applied over the next signal steps
+    pub fn encode<E: Encoder>(&mut self,
+                              name: &str,
+                              encoder: &E,
+                              values: &[f32])
+                              -> Result<(), CajalError> {
+        let cells = match self.inputs.iter().find(|i| i.name() == name) {
+            Some(input) => input.cells().len(),
+            None => return Err(missing_input(name)),
+        };
+        let schedule = try!(encoder.encode(values, cells));
+        self.queue(name, schedule)
+    }
+
+    // Queues a schedule that was encoded beforehand on the named input region
+    pub fn queue(&mut self, name: &str, schedule: Schedule) -> Result<(), CajalError> {
+        match self.inputs.iter_mut().find(|i| i.name() == name) {
+            Some(input) => input.queue(schedule),
+            None => Err(missing_input(name)),
+        }
+    }
+
+    // Registers a region whose activity is read after every signal step, replacing any
+    // region of the same name.  Every cell has to lie within the grid
+    pub fn add_output(&mut self, name: &str, region: Region) -> Result<(), CajalError> {
+        try!(region.check(self.width(), self.height()));
+        self.outputs.retain(|o| o.name() != name);
+        self.outputs.push(Output::new(name, region));
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    // What the named region did during the last signal step
+    pub fn readout(&self, name: &str) -> Option<&Readout> {
+        self.outputs.iter().find(|o| o.name() == name).map(|o| o.readout())
+    }
+
+    // Unlike `set_input`, a signal too large for a cell is an error rather than saturating
+    pub fn try_set_input(&mut self, x: u32, y: u32, sig: u8) -> Result<(), CajalError> {
+        try!(self.check_bounds(x, y));
+        try!(check_signal(sig));
+        self.grid.set_input(x, y, sig);
+        Ok(())
+    }
+
+    fn check_bounds(&self, x: u32, y: u32) -> Result<(), CajalError> {
+        if x < self.width() && y < self.height() {
+            Ok(())
+        } else {
+            Err(CajalError::OutOfBounds {
+                x: x,
+                y: y,
+                width: self.width(),
+                height: self.height(),
+            })
+        }
+    }
+
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(snapshot::write_header(writer));
+        self.grid.save(writer)
+    }
+
+    // Snapshots don't hold the model and growth rule, so a grid saved with others than
+    // the default ones has to be loaded with them again
+    pub fn load_with_rules<R: Read>(reader: &mut R,
+                                    model: M,
+                                    growth: G)
+                                    -> io::Result<Cajal<M, G>> {
+        try!(snapshot::read_header(reader));
+        Ok(Cajal {
+            grid: try!(Grid::load(reader, model, growth)),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            raster: None,
+        })
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        self.grid.state_hash()
+    }
+}
+// Validates the config for the given kind of grid and makes sure every cell and page can
+// be addressed
+fn check_config<N: Neighbourhood>(config: &CajalConfig) -> Result<(), CajalError> {
+    try!(config.validate_for::<N>());
+
+    let (pages_x, pages_y, pages_z) = (config.pages_x(), config.pages_y(), config.pages_z());
+    if pages_x.checked_mul(N::page_width()).is_none() ||
+       pages_y.checked_mul(N::page_width()).is_none() ||
+       pages_z.checked_mul(N::page_depth()).is_none() ||
+       pages_x.checked_mul(pages_y).and_then(|n| n.checked_mul(pages_z)).is_none() {
+        return Err(CajalError::Overflow(format!("a grid of {}x{}x{} pages is too large to \
+                                                 address",
+                                                pages_x,
+                                                pages_y,
+                                                pages_z)));
+    }
+
+    Ok(())
+}
+
+fn check_signal(sig: u8) -> Result<(), CajalError> {
+    if sig > 63 {
+        return Err(CajalError::Overflow(format!("signal {} does not fit in a cell, the maximum \
+                                                 is 63",
+                                                sig)));
+    }
+    Ok(())
+}
+
+fn missing_input(name: &str) -> CajalError {
+    CajalError::Encoding(format!("there is no input region named {}", name))
+}
+
+
+
+
+// A grid of cubic pages where cells also grow and signal up and down
+#[derive(Clone)]
+pub struct Cajal3D<M = CoDi, G = ChromosomeGrowth> {
+    grid: Grid<M, G, Gate3D>,
 }
 
+impl Cajal3D {
+    // `size` pages along every axis.  Panics if the parameters are invalid, see `try_new`
+    pub fn new(size: u32, density: f32, seed: &[usize]) -> Cajal3D {
+        match Cajal3D::try_new(size, density, seed) {
+            Ok(cajal) => cajal,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_new(size: u32, density: f32, seed: &[usize]) -> Result<Cajal3D, CajalError> {
+        Cajal3D::try_with_config(&CajalConfig {
+            size: size,
+            depth: Some(size),
+            density: density,
+            seed: seed.to_vec(),
+            ..CajalConfig::default()
+        })
+    }
+
+    pub fn with_config(config: &CajalConfig) -> Cajal3D {
+        match Cajal3D::try_with_config(config) {
+            Ok(cajal) => cajal,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_with_config(config: &CajalConfig) -> Result<Cajal3D, CajalError> {
+        Cajal3D::try_with_rules(config, CoDi, ChromosomeGrowth)
+    }
+}
+
+impl<M: NeuronModel<Gate3D>, G: GrowthRule<Gate3D>> Cajal3D<M, G> {
+    // Panics if the config is invalid, see `try_with_rules`
+    pub fn with_rules(config: &CajalConfig, model: M, growth: G) -> Cajal3D<M, G> {
+        match Cajal3D::try_with_rules(config, model, growth) {
+            Ok(cajal) => cajal,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_with_rules(config: &CajalConfig,
+                          model: M,
+                          growth: G)
+                          -> Result<Cajal3D<M, G>, CajalError> {
+        try!(check_config::<Gate3D>(config));
+        Ok(Cajal3D { grid: Grid::with_rules(config, model, growth) })
+    }
+
+    pub fn grow(&mut self) {
+        self.grid.grow();
+    }
+
+    pub fn grow_step(&mut self) -> StepReport {
+        self.grid.grow_step()
+    }
+
+    // Side length of a cubic grid, same as `width()`
+    pub fn dimension(&self) -> u32 {
+        self.grid.width()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.grid.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.grid.height()
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.grid.depth()
+    }
+
+    pub fn get_cell(&self, x: u32, y: u32, z: u32) -> &Cell {
+        self.grid.cell_at(x, y, z)
+    }
+
+    pub fn signal(&mut self) {
+        self.grid.signal();
+    }
+
+    pub fn signal_step(&mut self) -> StepReport {
+        self.grid.signal_step()
+    }
+
+    pub fn try_get_cell(&self, x: u32, y: u32, z: u32) -> Result<&Cell, CajalError> {
+        try!(self.check_bounds(x, y, z));
+        Ok(self.grid.cell_at(x, y, z))
+    }
+
+    pub fn set_input(&mut self, x: u32, y: u32, z: u32, sig: u8) {
+        self.grid.set_input_at(x, y, z, sig);
+    }
 
+    pub fn try_set_input(&mut self, x: u32, y: u32, z: u32, sig: u8) -> Result<(), CajalError> {
+        try!(self.check_bounds(x, y, z));
+        try!(check_signal(sig));
+        self.grid.set_input_at(x, y, z, sig);
+        Ok(())
+    }
 
+    fn check_bounds(&self, x: u32, y: u32, z: u32) -> Result<(), CajalError> {
+        if x < self.width() && y < self.height() && z < self.depth() {
+            Ok(())
+        } else {
+            Err(CajalError::OutOfBounds3D {
+                x: x,
+                y: y,
+                z: z,
+                width: self.width(),
+                height: self.height(),
+                depth: self.depth(),
+            })
+        }
+    }
+
+    // Partly estimated, see `MemoryUsage`
+    pub fn memory_usage(&self) -> MemoryReport {
+        self.grid.memory_usage()
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        self.grid.state_hash()
+    }
+}
 
 
 #[cfg(test)]
 mod tests {
-    use super::Cajal;
+    use super::{Cajal, PAGE_WIDTH};
+    use std::io::Cursor;
     use test::Bencher;
 
+    fn assert_same_cells(a: &Cajal, b: &Cajal, dimension: u32) {
+        for x in 0..dimension {
+            for y in 0..dimension {
+                assert!(a.get_cell(x, y).raw() == b.get_cell(x, y).raw());
+            }
+        }
+    }
+
     #[test]
     fn default_params() {
         let _ = Cajal::default();
     }
 
+    #[test]
+    fn snapshot_resume_grow() {
+        let mut cajal = Cajal::new(2, 0.01, &[1, 2, 3, 4]);
+        cajal.grow_step();
+        cajal.grow_step();
+
+        let mut buf = Vec::new();
+        cajal.save(&mut buf).unwrap();
+        let mut restored = Cajal::load(&mut Cursor::new(buf)).unwrap();
+
+        loop {
+            let active = cajal.grow_step().active_cells;
+            assert!(restored.grow_step().active_cells == active);
+            if active == 0 {
+                break;
+            }
+        }
+        assert_same_cells(&cajal, &restored, 2 * PAGE_WIDTH);
+    }
+
+    #[test]
+    fn snapshot_resume_signal() {
+        let mut cajal = Cajal::new(1, 0.01, &[1, 2, 3, 4]);
+        cajal.grow();
+        for i in 1..PAGE_WIDTH - 1 {
+            cajal.set_input(i, i, 63);
+        }
+        cajal.signal_step();
+
+        let mut buf = Vec::new();
+        cajal.save(&mut buf).unwrap();
+        let mut restored = Cajal::load(&mut Cursor::new(buf)).unwrap();
+
+        for _ in 0..10 {
+            assert!(cajal.signal_step().active_cells == restored.signal_step().active_cells);
+        }
+        assert_same_cells(&cajal, &restored, PAGE_WIDTH);
+    }
+
+    #[test]
+    fn snapshot_rejects_garbage() {
+        let mut reader = Cursor::new(b"CAJL\xff\x00\x00\x00".to_vec());
+        assert!(Cajal::load(&mut reader).is_err());
+    }
+
     #[bench]
     fn bench_new_5x5(b: &mut Bencher) {
         b.iter(|| {
@@ -0,0 +1,164 @@
+use roaring::RoaringBitmap;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::ops::Add;
+
+// Bytes held by a page, or by several pages summed up.  Vectors are counted by their
+// allocated capacities rather than by how much of them is in use.  Hash maps don't tell
+// how much they allocated, so those are estimates
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct MemoryUsage {
+    pub cells: usize,
+    // Active, charged and firing cells
+    pub bitmaps: usize,
+    // Pending growth, on the page and bound for other pages
+    pub changes: usize,
+    // Signal queues, refractory counters and the lists of fired bodies and deliveries
+    pub signals: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.cells + self.bitmaps + self.changes + self.signals
+    }
+}
+
+impl Add for MemoryUsage {
+    type Output = MemoryUsage;
+
+    fn add(self, other: MemoryUsage) -> MemoryUsage {
+        MemoryUsage {
+            cells: self.cells + other.cells,
+            bitmaps: self.bitmaps + other.bitmaps,
+            changes: self.changes + other.changes,
+            signals: self.signals + other.signals,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryReport {
+    // One entry per page, row by row
+    pub pages: Vec<MemoryUsage>,
+    // Spike timing state shared by the whole grid
+    pub plasticity: usize,
+    // Buffers that growth, signal and Hebbian updates crossing page edges are routed through
+    pub routing: usize,
+    pub raster: usize,
+}
+
+impl MemoryReport {
+    pub fn sum(&self) -> MemoryUsage {
+        self.pages.iter().fold(MemoryUsage::default(), |acc, page| acc + *page)
+    }
+
+    pub fn total(&self) -> usize {
+        self.sum().total() + self.plasticity + self.routing + self.raster
+    }
+}
+
+
+pub fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * mem::size_of::<T>()
+}
+
+// An estimate.  `capacity()` is only what fits below the load factor, the table itself
+// has a power of two buckets of roughly a tenth more, each with a hash, a key and a value
+pub fn map_bytes<K: Eq + Hash, V>(map: &HashMap<K, V>) -> usize {
+    let buckets = match map.capacity() {
+        0 => 0,
+        capacity => (capacity * 11 / 10).next_power_of_two(),
+    };
+    buckets * (mem::size_of::<u64>() + mem::size_of::<K>() + mem::size_of::<V>())
+}
+
+// Roaring splits values into containers by their upper 16 bits.  A container holds up
+// to 4096 values as a sorted array of u16, and switches to a fixed 8kB bitset past that
+pub fn bitmap_bytes(bitmap: &RoaringBitmap<u32>) -> usize {
+    let container = |values: usize| {
+        mem::size_of::<(u16, Vec<u16>)>() +
+        if values <= 4096 { values * mem::size_of::<u16>() } else { 8192 }
+    };
+
+    // Values come in order, so each container's run of values is counted in one go
+    let mut bytes = 0;
+    let mut current = None;
+    let mut values = 0;
+    for value in bitmap.iter() {
+        let key = value >> 16;
+        if current != Some(key) {
+            if current.is_some() {
+                bytes += container(values);
+            }
+            current = Some(key);
+            values = 0;
+        }
+        values += 1;
+    }
+    if current.is_some() {
+        bytes += container(values);
+    }
+    bytes
+}
+
+
+#[cfg(test)]
+mod test {
+    use roaring::RoaringBitmap;
+    use std::collections::HashMap;
+    use std::mem;
+    use super::{bitmap_bytes, map_bytes};
+    use {Cajal, PAGE_SIZE};
+
+    #[test]
+    fn bitmap_containers() {
+        let header = mem::size_of::<(u16, Vec<u16>)>();
+        let mut bitmap: RoaringBitmap<u32> = RoaringBitmap::new();
+        assert!(bitmap_bytes(&bitmap) == 0);
+
+        for i in 0..3 {
+            bitmap.insert(i);
+        }
+        assert!(bitmap_bytes(&bitmap) == header + 6);
+
+        for i in 0..5000 {
+            bitmap.insert(i);
+        }
+        assert!(bitmap_bytes(&bitmap) == header + 8192);
+
+        // Only containers that hold values count
+        bitmap.insert(16 << 16);
+        assert!(bitmap_bytes(&bitmap) == 2 * header + 8192 + 2);
+        bitmap.insert(3 << 16);
+        bitmap.insert((3 << 16) + 1);
+        assert!(bitmap_bytes(&bitmap) == 3 * header + 8192 + 3 * 2);
+    }
+
+    #[test]
+    fn map_buckets() {
+        let mut map: HashMap<u32, u32> = HashMap::new();
+        assert!(map_bytes(&map) == 0);
+        map.insert(1, 2);
+        let buckets = map_bytes(&map) / 16;
+        assert!(buckets.is_power_of_two() && buckets * 10 / 11 >= map.capacity());
+    }
+
+    #[test]
+    fn cajal_memory() {
+        let mut cajal = Cajal::new(2, 0.01, &[1, 2, 3, 4]);
+        cajal.grow_step();
+        let memory = cajal.memory_usage();
+
+        assert!(memory.pages.len() == 4);
+        assert!(memory.pages.iter().all(|page| page.cells >= PAGE_SIZE as usize * 4));
+        assert!(memory.pages.iter().all(|page| page.bitmaps > 0));
+        assert!(memory.routing > 0);
+        assert!(memory.total() ==
+                memory.sum().total() + memory.plasticity + memory.routing + memory.raster);
+
+        cajal.start_raster();
+        cajal.signal_step();
+        assert!(cajal.memory_usage().raster > 0);
+    }
+}
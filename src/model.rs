@@ -0,0 +1,221 @@
+use config::CajalConfig;
+use grid::{Cell, CellType, Gate, Neighbourhood};
+
+// Bits of CoDi's per-cell state: input steps a body still ignores, and whether it holds
+// signal that leaks away
+const REFRACTORY_MASK: u64 = 0xffff;
+const CHARGED: u64 = 1 << 16;
+
+// A signal arriving at a cell.  Potentiation of axon contacts has already been applied to
+// the strength
+#[derive(Debug, Copy, Clone)]
+pub struct Input<N = Gate> {
+    pub origin: CellType,
+    pub travel_direction: N,
+    pub strength: u8,
+    pub stim: bool,
+}
+
+// The arithmetic of signalling: when an active cell passes its signal on, and what an
+// arriving signal does to the cell it lands on.  Pages are processed in parallel, so a
+// model is shared between them.  Anything a model has to remember about a cell from one
+// step to the next goes into `state`, which pages keep for every cell where it isn't 0
+pub trait NeuronModel<N = Gate>: Send + Sync {
+    fn fires(&self, cell: &Cell) -> bool;
+    fn integrate(&self, cell: &mut Cell, input: &Input<N>);
+
+    // Called for every active cell once it has been checked for firing
+    fn checked(&self, _: &CajalConfig, _: &mut Cell, _: bool, _: &mut u64) {}
+
+    // Whether the cell takes up signal arriving this step.  Cells that don't also don't
+    // become active
+    fn accepts(&self, _: &Cell, _: u64) -> bool {
+        true
+    }
+
+    // Called once a step for every cell with state, after every active cell has been
+    // checked and before any signal arrives
+    fn tick(&self, _: &CajalConfig, _: &mut Cell, _: &mut u64) {}
+}
+
+// The CoDi rule: cells fire once their signal reaches the threshold.  Axons only relay
+// signal that came in through their gate, contacts from an axon excite or inhibit and
+// everything else sums up what it receives.  Bodies leak and go refractory as `leak` and
+// `refractory` in the config ask for
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CoDi;
+
+impl<N: Neighbourhood> NeuronModel<N> for CoDi {
+    fn fires(&self, cell: &Cell) -> bool {
+        cell.get_signal() >= cell.get_threshold()
+    }
+
+    fn integrate(&self, cell: &mut Cell, input: &Input<N>) {
+        match (input.origin, cell.get_cell_type()) {
+            (CellType::Axon, CellType::Axon) => {
+                // The side the signal arrived from, which has to be the axon's gate
+                let direction = !input.travel_direction;
+
+                debug!("{:?} == {:?}?", direction, N::of(cell));
+                if direction == N::of(cell) {
+                    cell.add_signal(input.strength);
+                }
+            }
+            (CellType::Axon, CellType::Dendrite) | (CellType::Axon, CellType::Body) => {
+                if input.stim == true {
+                    cell.add_signal(input.strength)
+                } else {
+                    cell.sub_signal(input.strength)
+                }
+            }
+            (CellType::Dendrite, CellType::Dendrite) |
+            (CellType::Dendrite, CellType::Body) |
+            (CellType::Body, CellType::Dendrite) |
+            (CellType::Body, CellType::Body) |
+            (CellType::Body, CellType::Axon) => cell.add_signal(input.strength),
+            (_, _) => {}
+        }
+    }
+
+    fn checked(&self, config: &CajalConfig, cell: &mut Cell, fired: bool, state: &mut u64) {
+        if cell.get_cell_type() != CellType::Body {
+            return;
+        }
+
+        if fired {
+            if config.refractory > 0 {
+                // The tick of this very step already counts down once
+                cell.clear_signal();
+                *state = (*state & !REFRACTORY_MASK) | (config.refractory as u64 + 1);
+            }
+        } else if config.leak > 0 && cell.get_signal() > 0 {
+            *state |= CHARGED;
+        }
+    }
+
+    fn accepts(&self, _: &Cell, state: u64) -> bool {
+        state & REFRACTORY_MASK == 0
+    }
+
+    fn tick(&self, config: &CajalConfig, cell: &mut Cell, state: &mut u64) {
+        if *state & REFRACTORY_MASK > 0 {
+            *state -= 1;
+        }
+        if *state & CHARGED != 0 {
+            cell.sub_signal(config.leak);
+            if cell.get_signal() == 0 {
+                *state &= !CHARGED;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::{CoDi, Input, NeuronModel};
+    use config::CajalConfig;
+    use grid::{Cell, CellType, Gate, Gate3D};
+    use {Cajal, ChromosomeGrowth, PAGE_WIDTH};
+
+    fn input<N>(origin: CellType, travel_direction: N, stim: bool) -> Input<N> {
+        Input {
+            origin: origin,
+            travel_direction: travel_direction,
+            strength: 3,
+            stim: stim,
+        }
+    }
+
+    #[test]
+    fn codi() {
+        let mut axon = Cell::new();
+        axon.set_cell_type(CellType::Axon);
+        axon.set_gate(Gate::West);
+        CoDi.integrate(&mut axon, &input(CellType::Axon, Gate::West, true));
+        assert!(axon.get_signal() == 0);
+        CoDi.integrate(&mut axon, &input(CellType::Axon, Gate::East, true));
+        assert!(axon.get_signal() == 3);
+
+        let mut body = Cell::new();
+        body.set_cell_type(CellType::Body);
+        body.set_threshold(5);
+        CoDi.integrate(&mut body, &input(CellType::Dendrite, Gate::North, false));
+        CoDi.integrate(&mut body, &input(CellType::Axon, Gate::North, true));
+        assert!(body.get_signal() == 6 && NeuronModel::<Gate>::fires(&CoDi, &body));
+        CoDi.integrate(&mut body, &input(CellType::Axon, Gate::North, false));
+        assert!(body.get_signal() == 3 && !NeuronModel::<Gate>::fires(&CoDi, &body));
+    }
+
+    #[test]
+    fn codi_3d() {
+        let mut axon = Cell::new();
+        axon.set_cell_type(CellType::Axon);
+        axon.set_gate_3d(Gate3D::Down);
+        CoDi.integrate(&mut axon, &input(CellType::Axon, Gate3D::South, true));
+        assert!(axon.get_signal() == 0);
+        CoDi.integrate(&mut axon, &input(CellType::Axon, Gate3D::Up, true));
+        assert!(axon.get_signal() == 3);
+    }
+
+    #[test]
+    fn codi_state() {
+        let config = CajalConfig { leak: 2, refractory: 1, ..CajalConfig::default() };
+        let codi: &NeuronModel<Gate> = &CoDi;
+
+        let mut body = Cell::new();
+        body.set_cell_type(CellType::Body);
+        body.set_threshold(5);
+        body.set_signal(6);
+        let mut state = 0;
+        codi.checked(&config, &mut body, true, &mut state);
+        assert!(body.get_signal() == 0);
+        for &accepts in &[false, true] {
+            codi.tick(&config, &mut body, &mut state);
+            assert!(codi.accepts(&body, state) == accepts);
+        }
+        assert!(state == 0);
+
+        body.set_signal(3);
+        codi.checked(&config, &mut body, false, &mut state);
+        codi.tick(&config, &mut body, &mut state);
+        assert!(body.get_signal() == 1 && state != 0);
+        codi.tick(&config, &mut body, &mut state);
+        assert!(body.get_signal() == 0 && state == 0);
+    }
+
+    struct Silent;
+
+    impl NeuronModel for Silent {
+        fn fires(&self, _: &Cell) -> bool {
+            false
+        }
+
+        fn integrate(&self, _: &mut Cell, _: &Input) {}
+    }
+
+    #[test]
+    fn custom_model() {
+        let config = CajalConfig { size: 1, density: 0.01, ..CajalConfig::default() };
+        let mut codi = Cajal::with_config(&config);
+        let mut silent = Cajal::with_model(&config, Silent);
+        codi.grow();
+        silent.grow();
+
+        for i in 1..PAGE_WIDTH - 1 {
+            codi.set_input(i, i, 63);
+            silent.set_input(i, i, 63);
+        }
+        assert!(codi.signal_step().active_cells > 0);
+        assert!(silent.signal_step().active_cells == 0);
+
+        let mut buf = Vec::new();
+        silent.save(&mut buf).unwrap();
+        let mut restored = Cajal::load_with_rules(&mut Cursor::new(buf), Silent, ChromosomeGrowth)
+                               .unwrap();
+        assert!(restored.state_hash() == silent.state_hash());
+        restored.set_input(10, 10, 63);
+        assert!(restored.signal_step().active_cells == 0);
+    }
+}
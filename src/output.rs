@@ -0,0 +1,210 @@
+use roaring::RoaringBitmap;
+
+use error::CajalError;
+use grid::Grid;
+use growth::GrowthRule;
+use model::NeuronModel;
+
+// A set of cells read out together after every signal step
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    Rect {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    Cells(Vec<(u32, u32)>),
+}
+
+impl Region {
+    // The cells of the region, rectangles row by row.  Positions in this list are what the
+    // `firing` bitmap of a readout refers to
+    pub fn cells(&self) -> Vec<(u32, u32)> {
+        match *self {
+            Region::Rect { x, y, width, height } => {
+                let mut cells = Vec::with_capacity(width as usize * height as usize);
+                for j in y..y + height {
+                    for i in x..x + width {
+                        cells.push((i, j));
+                    }
+                }
+                cells
+            }
+            Region::Cells(ref cells) => cells.clone(),
+        }
+    }
+
+    // Fails unless every cell of the region lies within a grid of the given size, without
+    // listing the cells of a rectangle first
+    pub fn check(&self, grid_width: u32, grid_height: u32) -> Result<(), CajalError> {
+        let out_of_bounds = |x, y| {
+            CajalError::OutOfBounds {
+                x: x,
+                y: y,
+                width: grid_width,
+                height: grid_height,
+            }
+        };
+
+        match *self {
+            Region::Rect { x, y, width, height } => {
+                let (right, bottom) = match (x.checked_add(width), y.checked_add(height)) {
+                    (Some(right), Some(bottom)) => (right, bottom),
+                    _ => {
+                        return Err(CajalError::Overflow(format!("region {:?} reaches past the \
+                                                                 largest coordinate",
+                                                                self)))
+                    }
+                };
+                if right > grid_width || bottom > grid_height {
+                    return Err(out_of_bounds(right.saturating_sub(1), bottom.saturating_sub(1)));
+                }
+            }
+            Region::Cells(ref cells) => {
+                if let Some(&(x, y)) = cells.iter()
+                                            .find(|&&(x, y)| x >= grid_width || y >= grid_height) {
+                    return Err(out_of_bounds(x, y));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// What a region did during the last signal step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Readout {
+    // Cells that passed signal on
+    pub fired: u32,
+    // Signal held by the cells once the step was over
+    pub signal: u32,
+    pub firing: RoaringBitmap<u32>,
+}
+
+impl Default for Readout {
+    fn default() -> Readout {
+        Readout {
+            fired: 0,
+            signal: 0,
+            firing: RoaringBitmap::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Output {
+    name: String,
+    region: Region,
+    // Resolved once, so reading only ever touches the cells of the region
+    cells: Vec<(u32, u32)>,
+    readout: Readout,
+}
+
+impl Output {
+    pub fn new(name: &str, region: Region) -> Output {
+        Output {
+            name: name.to_owned(),
+            cells: region.cells(),
+            region: region,
+            readout: Readout::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn region(&self) -> &Region {
+        &self.region
+    }
+
+    pub fn readout(&self) -> &Readout {
+        &self.readout
+    }
+
+    pub fn read<M: NeuronModel, G: GrowthRule>(&mut self, grid: &Grid<M, G>) {
+        let mut readout = Readout::default();
+        for (i, &(x, y)) in self.cells.iter().enumerate() {
+            readout.signal += grid.get_cell(x, y).get_signal() as u32;
+            if grid.is_firing(x, y) {
+                readout.fired += 1;
+                readout.firing.insert(i as u32);
+            }
+        }
+        self.readout = readout;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::u32;
+    use super::Region;
+    use {Cajal, CajalError};
+
+    #[test]
+    fn region_cells() {
+        let rect = Region::Rect {
+            x: 3,
+            y: 5,
+            width: 2,
+            height: 2,
+        };
+        assert!(rect.cells() == vec![(3, 5), (4, 5), (3, 6), (4, 6)]);
+        assert!(Region::Cells(vec![(1, 1), (0, 7)]).cells() == vec![(1, 1), (0, 7)]);
+    }
+
+    #[test]
+    fn region_bounds() {
+        let rect = |x, width| {
+            Region::Rect {
+                x: x,
+                y: 0,
+                width: width,
+                height: u32::MAX,
+            }
+        };
+        assert!(rect(0, 256).check(256, u32::MAX).is_ok());
+        match rect(0, u32::MAX).check(256, 256) {
+            Err(CajalError::OutOfBounds { x, y, .. }) => assert!(x == u32::MAX - 1 && y == x),
+            _ => panic!("expected out of bounds"),
+        }
+        match rect(1, u32::MAX).check(256, 256) {
+            Err(CajalError::Overflow(_)) => {}
+            _ => panic!("expected overflow"),
+        }
+        assert!(Region::Cells(vec![(255, 0), (0, 256)]).check(256, 256).is_err());
+    }
+
+    #[test]
+    fn readout_follows_signal() {
+        let mut cajal = Cajal::new(1, 0.05, &[1, 2, 3, 4]);
+        cajal.grow();
+        let bodies = Region::Rect {
+            x: 0,
+            y: 0,
+            width: 256,
+            height: 256,
+        };
+        cajal.add_output("all", bodies).unwrap();
+        assert!(cajal.add_output("outside", Region::Cells(vec![(10, 256)])).is_err());
+
+        for i in 1..255 {
+            cajal.set_input(i, i, 63);
+        }
+        cajal.signal_step();
+
+        let readout = cajal.readout("all").unwrap().clone();
+        assert!(readout.fired > 0);
+        assert!(readout.fired == readout.firing.len());
+        let mut signal = 0;
+        for x in 0..256 {
+            for y in 0..256 {
+                signal += cajal.get_cell(x, y).get_signal() as u32;
+            }
+        }
+        assert!(readout.signal == signal);
+        assert!(cajal.readout("outside").is_none());
+    }
+}
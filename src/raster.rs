@@ -0,0 +1,189 @@
+use roaring::RoaringBitmap;
+use std::io::{self, Read, Write};
+
+use error::CajalError;
+use memory;
+use snapshot;
+
+const RASTER_MAGIC: &'static [u8; 4] = b"CJRS";
+const RASTER_VERSION: u32 = 1;
+
+// The bodies that crossed threshold during each signal step, one bitmap of `y * width + x`
+// indices per step.  Grids with more cells than a bitmap can index can't be recorded
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster {
+    width: u32,
+    height: u32,
+    steps: Vec<RoaringBitmap<u32>>,
+}
+
+impl Raster {
+    pub fn new(width: u32, height: u32) -> Result<Raster, CajalError> {
+        if width.checked_mul(height).is_none() {
+            return Err(CajalError::Overflow(format!("a {}x{} grid has too many cells for a \
+                                                     spike raster",
+                                                    width,
+                                                    height)));
+        }
+
+        Ok(Raster {
+            width: width,
+            height: height,
+            steps: Vec::new(),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Steps recorded so far
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn get(&self, step: usize) -> &RoaringBitmap<u32> {
+        &self.steps[step]
+    }
+
+    // Coordinates of the bodies that fired during `step`
+    pub fn fired(&self, step: usize) -> Vec<(u32, u32)> {
+        self.steps[step].iter().map(|i| (i % self.width, i / self.width)).collect()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        memory::vec_bytes(&self.steps) +
+        self.steps.iter().map(memory::bitmap_bytes).fold(0, |acc, x| acc + x)
+    }
+
+    pub fn push(&mut self, fired: &[(u32, u32)]) {
+        let mut bitmap = RoaringBitmap::new();
+        for &(x, y) in fired {
+            bitmap.insert(y * self.width + x);
+        }
+        self.steps.push(bitmap);
+    }
+
+    // One `step,x,y` line per spike, after a header
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(writeln!(writer, "step,x,y"));
+        for step in 0..self.steps.len() {
+            for (x, y) in self.fired(step) {
+                try!(writeln!(writer, "{},{},{}", step, x, y));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_binary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(writer.write_all(RASTER_MAGIC));
+        try!(snapshot::write_u32(writer, RASTER_VERSION));
+        try!(snapshot::write_u32(writer, self.width));
+        try!(snapshot::write_u32(writer, self.height));
+        try!(snapshot::write_u32(writer, self.steps.len() as u32));
+        for bitmap in &self.steps {
+            try!(snapshot::write_u32_slice(writer, &bitmap.iter().collect::<Vec<u32>>()));
+        }
+        Ok(())
+    }
+
+    pub fn read_binary<R: Read>(reader: &mut R) -> io::Result<Raster> {
+        let mut magic = [0u8; 4];
+        try!(reader.read_exact(&mut magic));
+        if &magic != RASTER_MAGIC {
+            return Err(snapshot::invalid_data("not a cajal spike raster"));
+        }
+        if try!(snapshot::read_u32(reader)) != RASTER_VERSION {
+            return Err(snapshot::invalid_data("unsupported spike raster version"));
+        }
+
+        let width = try!(snapshot::read_u32(reader));
+        let height = try!(snapshot::read_u32(reader));
+        let cells = match width.checked_mul(height) {
+            Some(cells) => cells,
+            None => return Err(snapshot::invalid_data("too many cells for a spike raster")),
+        };
+        let len = try!(snapshot::read_u32(reader));
+        let mut steps = Vec::with_capacity(snapshot::capacity(len));
+        for _ in 0..len {
+            let mut bitmap = RoaringBitmap::new();
+            for index in try!(snapshot::read_u32_vec(reader)) {
+                if index >= cells {
+                    return Err(snapshot::invalid_data("spike outside of the grid"));
+                }
+                bitmap.insert(index);
+            }
+            steps.push(bitmap);
+        }
+
+        Ok(Raster {
+            width: width,
+            height: height,
+            steps: steps,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::Raster;
+    use {Cajal, CellType};
+
+    #[test]
+    fn records_bodies() {
+        let mut cajal = Cajal::new(1, 0.05, &[1, 2, 3, 4]);
+        cajal.grow();
+        cajal.start_raster();
+        for i in 1..255 {
+            cajal.set_input(i, i, 63);
+        }
+        for _ in 0..5 {
+            cajal.signal_step();
+        }
+
+        let raster = cajal.stop_raster().unwrap();
+        assert!(cajal.raster().is_none());
+        assert!(raster.len() == 5);
+        let mut spikes = 0;
+        for step in 0..raster.len() {
+            for (x, y) in raster.fired(step) {
+                assert!(cajal.get_cell(x, y).get_cell_type() == CellType::Body);
+                spikes += 1;
+            }
+        }
+        assert!(spikes > 0);
+
+        let mut csv = Vec::new();
+        raster.write_csv(&mut csv).unwrap();
+        assert!(String::from_utf8(csv).unwrap().lines().count() == spikes + 1);
+
+        let mut binary = Vec::new();
+        raster.write_binary(&mut binary).unwrap();
+        assert!(Raster::read_binary(&mut Cursor::new(binary)).unwrap() == raster);
+        assert!(Raster::read_binary(&mut Cursor::new(b"CAJL".to_vec())).is_err());
+    }
+
+    #[test]
+    fn too_many_cells() {
+        assert!(Raster::new(65536, 65535).is_ok());
+        assert!(Raster::new(65536, 65536).is_err());
+
+        let mut binary = Vec::new();
+        Raster::new(256, 256).unwrap().write_binary(&mut binary).unwrap();
+        // Height 65536, with width 256 that is 2^24 cells
+        binary[12..16].copy_from_slice(&[0, 0, 1, 0]);
+        assert!(Raster::read_binary(&mut Cursor::new(binary.clone())).is_ok());
+        binary[8..12].copy_from_slice(&[0, 0, 1, 0]);
+        assert!(Raster::read_binary(&mut Cursor::new(binary)).is_err());
+    }
+}
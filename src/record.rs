@@ -0,0 +1,494 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::{missing_input, Cajal, CajalError};
+use config::CajalConfig;
+use encode::{Encoder, Schedule};
+use output::Region;
+use report::StepReport;
+use snapshot::{self, Snapshot};
+
+const LOG_MAGIC: &'static [u8; 4] = b"CJLG";
+const LOG_VERSION: u32 = 1;
+
+const EVENT_NEW: u8 = 0;
+const EVENT_GROW_STEP: u8 = 1;
+const EVENT_SIGNAL_STEP: u8 = 2;
+const EVENT_SET_INPUT: u8 = 3;
+const EVENT_ADD_INPUT: u8 = 4;
+const EVENT_QUEUE: u8 = 5;
+const EVENT_ADD_OUTPUT: u8 = 6;
+
+const REGION_RECT: u8 = 0;
+const REGION_CELLS: u8 = 1;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    // A call that was refused, while recording or when the log asks for it
+    Cajal(CajalError),
+    Diverged {
+        step: u64,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Io(ref e) => write!(f, "failed to read event log: {}", e),
+            ReplayError::Cajal(ref e) => write!(f, "{}", e),
+            ReplayError::Diverged { step, expected, actual } => {
+                write!(f,
+                       "replay diverged at step {}: expected state {:#x}, found {:#x}",
+                       step,
+                       expected,
+                       actual)
+            }
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn description(&self) -> &str {
+        match *self {
+            ReplayError::Io(ref e) => e.description(),
+            ReplayError::Cajal(ref e) => e.description(),
+            ReplayError::Diverged { .. } => "replay diverged from the recorded run",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ReplayError::Io(ref e) => Some(e),
+            ReplayError::Cajal(ref e) => Some(e),
+            ReplayError::Diverged { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> ReplayError {
+        ReplayError::Io(e)
+    }
+}
+
+impl From<CajalError> for ReplayError {
+    fn from(e: CajalError) -> ReplayError {
+        ReplayError::Cajal(e)
+    }
+}
+
+
+pub struct Recorder<W: Write> {
+    cajal: Cajal,
+    writer: W,
+    hashes: bool,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W,
+               size: u32,
+               density: f32,
+               seed: &[usize],
+               hashes: bool)
+               -> Result<Recorder<W>, ReplayError> {
+        let config = CajalConfig {
+            size: size,
+            density: density,
+            seed: seed.to_vec(),
+            ..CajalConfig::default()
+        };
+        Recorder::with_config(writer, &config, hashes)
+    }
+
+    pub fn with_config(mut writer: W,
+                       config: &CajalConfig,
+                       hashes: bool)
+                       -> Result<Recorder<W>, ReplayError> {
+        let cajal = try!(Cajal::try_with_config(config));
+        try!(writer.write_all(LOG_MAGIC));
+        try!(snapshot::write_u32(&mut writer, LOG_VERSION));
+
+        try!(snapshot::write_u8(&mut writer, EVENT_NEW));
+        try!(config.save(&mut writer));
+
+        Ok(Recorder {
+            cajal: cajal,
+            writer: writer,
+            hashes: hashes,
+        })
+    }
+
+    pub fn grow(&mut self) -> io::Result<()> {
+        loop {
+            if try!(self.grow_step()).active_cells == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn grow_step(&mut self) -> io::Result<StepReport> {
+        let report = self.cajal.grow_step();
+        try!(self.write_step(EVENT_GROW_STEP));
+        Ok(report)
+    }
+
+    pub fn signal(&mut self) -> io::Result<()> {
+        loop {
+            let report = try!(self.signal_step());
+            if self.cajal.is_settled(&report) {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn signal_step(&mut self) -> io::Result<StepReport> {
+        let report = self.cajal.signal_step();
+        try!(self.write_step(EVENT_SIGNAL_STEP));
+        Ok(report)
+    }
+
+    // Calls that fail are not recorded
+    pub fn set_input(&mut self, x: u32, y: u32, sig: u8) -> Result<(), ReplayError> {
+        try!(self.cajal.try_set_input(x, y, sig));
+        try!(snapshot::write_u8(&mut self.writer, EVENT_SET_INPUT));
+        try!(snapshot::write_u32(&mut self.writer, x));
+        try!(snapshot::write_u32(&mut self.writer, y));
+        try!(snapshot::write_u8(&mut self.writer, sig));
+        Ok(())
+    }
+
+    pub fn add_input(&mut self, name: &str, region: Region) -> Result<(), ReplayError> {
+        try!(self.cajal.add_input(name, region.clone()));
+        try!(snapshot::write_u8(&mut self.writer, EVENT_ADD_INPUT));
+        try!(write_name(&mut self.writer, name));
+        try!(write_region(&mut self.writer, &region));
+        Ok(())
+    }
+
+    // The encoded schedule is what gets recorded, so replaying doesn't need the encoder
+    pub fn encode<E: Encoder>(&mut self,
+                              name: &str,
+                              encoder: &E,
+                              values: &[f32])
+                              -> Result<(), ReplayError> {
+        let cells = match self.cajal.inputs().iter().find(|i| i.name() == name) {
+            Some(input) => input.cells().len(),
+            None => return Err(ReplayError::Cajal(missing_input(name))),
+        };
+        let schedule = try!(encoder.encode(values, cells));
+        try!(self.cajal.queue(name, schedule.clone()));
+        try!(snapshot::write_u8(&mut self.writer, EVENT_QUEUE));
+        try!(write_name(&mut self.writer, name));
+        try!(write_schedule(&mut self.writer, &schedule));
+        Ok(())
+    }
+
+    pub fn add_output(&mut self, name: &str, region: Region) -> Result<(), ReplayError> {
+        try!(self.cajal.add_output(name, region.clone()));
+        try!(snapshot::write_u8(&mut self.writer, EVENT_ADD_OUTPUT));
+        try!(write_name(&mut self.writer, name));
+        try!(write_region(&mut self.writer, &region));
+        Ok(())
+    }
+
+    pub fn cajal(&self) -> &Cajal {
+        &self.cajal
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> (Cajal, W) {
+        (self.cajal, self.writer)
+    }
+
+    fn write_step(&mut self, event: u8) -> io::Result<()> {
+        try!(snapshot::write_u8(&mut self.writer, event));
+        try!(snapshot::write_bool(&mut self.writer, self.hashes));
+        if self.hashes {
+            try!(snapshot::write_u64(&mut self.writer, self.cajal.state_hash()));
+        }
+        Ok(())
+    }
+}
+
+
+pub struct Replayer<R: Read> {
+    reader: R,
+    verify: bool,
+}
+
+impl<R: Read> Replayer<R> {
+    pub fn new(reader: R) -> Replayer<R> {
+        Replayer {
+            reader: reader,
+            verify: false,
+        }
+    }
+
+    pub fn verify(mut self, verify: bool) -> Replayer<R> {
+        self.verify = verify;
+        self
+    }
+
+    pub fn replay(&mut self) -> Result<Cajal, ReplayError> {
+        let mut magic = [0u8; 4];
+        try!(self.reader.read_exact(&mut magic));
+        if &magic != LOG_MAGIC {
+            return Err(ReplayError::Io(snapshot::invalid_data("not a cajal event log")));
+        }
+        if try!(snapshot::read_u32(&mut self.reader)) != LOG_VERSION {
+            return Err(ReplayError::Io(snapshot::invalid_data("unsupported event log version")));
+        }
+
+        if try!(self.next_event()) != Some(EVENT_NEW) {
+            return Err(ReplayError::Io(snapshot::invalid_data("event log must start with new")));
+        }
+        let config = try!(CajalConfig::load(&mut self.reader));
+
+        debug!("Replaying run with {:?}", config);
+        let mut cajal = try!(Cajal::try_with_config(&config));
+        let mut step = 0u64;
+
+        while let Some(event) = try!(self.next_event()) {
+            match event {
+                EVENT_GROW_STEP => {
+                    cajal.grow_step();
+                    try!(self.check_step(&cajal, step));
+                    step += 1;
+                }
+                EVENT_SIGNAL_STEP => {
+                    cajal.signal_step();
+                    try!(self.check_step(&cajal, step));
+                    step += 1;
+                }
+                EVENT_SET_INPUT => {
+                    let x = try!(snapshot::read_u32(&mut self.reader));
+                    let y = try!(snapshot::read_u32(&mut self.reader));
+                    let sig = try!(snapshot::read_u8(&mut self.reader));
+                    try!(cajal.try_set_input(x, y, sig));
+                }
+                EVENT_ADD_INPUT => {
+                    let name = try!(read_name(&mut self.reader));
+                    let region = try!(read_region(&mut self.reader));
+                    try!(cajal.add_input(&name, region));
+                }
+                EVENT_QUEUE => {
+                    let name = try!(read_name(&mut self.reader));
+                    let schedule = try!(read_schedule(&mut self.reader));
+                    try!(cajal.queue(&name, schedule));
+                }
+                EVENT_ADD_OUTPUT => {
+                    let name = try!(read_name(&mut self.reader));
+                    let region = try!(read_region(&mut self.reader));
+                    try!(cajal.add_output(&name, region));
+                }
+                _ => return Err(ReplayError::Io(snapshot::invalid_data("unknown event"))),
+            }
+        }
+
+        debug!("Replayed {} steps", step);
+        Ok(cajal)
+    }
+
+    fn next_event(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0u8; 1];
+        loop {
+            return match self.reader.read(&mut buf) {
+                Ok(0) => Ok(None),
+                Ok(_) => Ok(Some(buf[0])),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+        }
+    }
+
+    fn check_step(&mut self, cajal: &Cajal, step: u64) -> Result<(), ReplayError> {
+        if !try!(snapshot::read_bool(&mut self.reader)) {
+            return Ok(());
+        }
+
+        let expected = try!(snapshot::read_u64(&mut self.reader));
+        if self.verify {
+            let actual = cajal.state_hash();
+            if actual != expected {
+                return Err(ReplayError::Diverged {
+                    step: step,
+                    expected: expected,
+                    actual: actual,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_name<W: Write>(writer: &mut W, name: &str) -> io::Result<()> {
+    try!(snapshot::write_u32(writer, name.len() as u32));
+    writer.write_all(name.as_bytes())
+}
+
+fn read_name<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = try!(snapshot::read_u32(reader));
+    let mut name = String::new();
+    if try!(reader.take(len as u64).read_to_string(&mut name)) != len as usize {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated region name"));
+    }
+    Ok(name)
+}
+
+fn write_region<W: Write>(writer: &mut W, region: &Region) -> io::Result<()> {
+    match *region {
+        Region::Rect { x, y, width, height } => {
+            try!(snapshot::write_u8(writer, REGION_RECT));
+            try!(snapshot::write_u32(writer, x));
+            try!(snapshot::write_u32(writer, y));
+            try!(snapshot::write_u32(writer, width));
+            snapshot::write_u32(writer, height)
+        }
+        Region::Cells(ref cells) => {
+            try!(snapshot::write_u8(writer, REGION_CELLS));
+            let flat: Vec<u32> = cells.iter().flat_map(|&(x, y)| vec![x, y]).collect();
+            snapshot::write_u32_slice(writer, &flat)
+        }
+    }
+}
+
+fn read_region<R: Read>(reader: &mut R) -> io::Result<Region> {
+    match try!(snapshot::read_u8(reader)) {
+        REGION_RECT => {
+            Ok(Region::Rect {
+                x: try!(snapshot::read_u32(reader)),
+                y: try!(snapshot::read_u32(reader)),
+                width: try!(snapshot::read_u32(reader)),
+                height: try!(snapshot::read_u32(reader)),
+            })
+        }
+        REGION_CELLS => {
+            let flat = try!(snapshot::read_u32_vec(reader));
+            if flat.len() % 2 != 0 {
+                return Err(snapshot::invalid_data("region cell without a y coordinate"));
+            }
+            Ok(Region::Cells(flat.chunks(2).map(|c| (c[0], c[1])).collect()))
+        }
+        _ => Err(snapshot::invalid_data("invalid region")),
+    }
+}
+
+fn write_schedule<W: Write>(writer: &mut W, schedule: &Schedule) -> io::Result<()> {
+    try!(snapshot::write_u32(writer, schedule.steps.len() as u32));
+    for step in &schedule.steps {
+        try!(snapshot::write_u32(writer, step.len() as u32));
+        for &(position, signal) in step {
+            try!(snapshot::write_u32(writer, position));
+            try!(snapshot::write_u8(writer, signal));
+        }
+    }
+    Ok(())
+}
+
+fn read_schedule<R: Read>(reader: &mut R) -> io::Result<Schedule> {
+    let len = try!(snapshot::read_u32(reader));
+    let mut steps = Vec::with_capacity(snapshot::capacity(len));
+    for _ in 0..len {
+        let n = try!(snapshot::read_u32(reader));
+        let mut step = Vec::with_capacity(snapshot::capacity(n));
+        for _ in 0..n {
+            let position = try!(snapshot::read_u32(reader));
+            step.push((position, try!(snapshot::read_u8(reader))));
+        }
+        steps.push(step);
+    }
+    Ok(Schedule { steps: steps })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Recorder, Replayer, ReplayError};
+    use std::io::Cursor;
+    use encode::Rate;
+    use {CajalError, Region};
+
+    fn record() -> (u64, Vec<u8>) {
+        let mut recorder = Recorder::new(Vec::new(), 1, 0.01, &[1, 2, 3, 4], true).unwrap();
+        recorder.grow().unwrap();
+        for i in 1..20 {
+            recorder.set_input(i * 10, i * 10, 63).unwrap();
+        }
+        for _ in 0..5 {
+            recorder.signal_step().unwrap();
+        }
+
+        let (cajal, log) = recorder.into_inner();
+        (cajal.state_hash(), log)
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let (hash, log) = record();
+        let cajal = Replayer::new(Cursor::new(log)).verify(true).replay().unwrap();
+        assert!(cajal.state_hash() == hash);
+    }
+
+    #[test]
+    fn replay_reports_divergence() {
+        let (_, mut log) = record();
+
+        // Corrupt the hash of the final signal step
+        let len = log.len();
+        log[len - 1] ^= 0xff;
+
+        match Replayer::new(Cursor::new(log.clone())).verify(true).replay() {
+            Err(ReplayError::Diverged { .. }) => {}
+            _ => panic!("divergence was not reported"),
+        }
+
+        // Without verification the corrupt hash is ignored
+        assert!(Replayer::new(Cursor::new(log)).replay().is_ok());
+    }
+
+    #[test]
+    fn signal_waits_for_encoded_input() {
+        let mut recorder = Recorder::new(Vec::new(), 1, 0.0, &[1, 2, 3, 4], false).unwrap();
+        recorder.add_input("in", Region::Cells(vec![(8, 8)])).unwrap();
+        recorder.encode("in", &Rate::new(4), &[1.0]).unwrap();
+        recorder.signal().unwrap();
+
+        let (cajal, _) = recorder.into_inner();
+        assert!(cajal.inputs.iter().all(|input| input.pending() == 0));
+    }
+
+    #[test]
+    fn replay_encoded_inputs() {
+        let mut recorder = Recorder::new(Vec::new(), 1, 0.01, &[1, 2, 3, 4], true).unwrap();
+        recorder.grow().unwrap();
+        let region = Region::Rect {
+            x: 8,
+            y: 8,
+            width: 16,
+            height: 16,
+        };
+        recorder.add_input("in", region.clone()).unwrap();
+        recorder.add_output("out", region).unwrap();
+        recorder.encode("in", &Rate::new(4), &[1.0, 0.5, 0.25]).unwrap();
+        match recorder.set_input(256, 0, 63) {
+            Err(ReplayError::Cajal(CajalError::OutOfBounds { .. })) => {}
+            _ => panic!("expected out of bounds"),
+        }
+        assert!(recorder.encode("missing", &Rate::new(4), &[1.0]).is_err());
+        for _ in 0..5 {
+            recorder.signal_step().unwrap();
+        }
+
+        let (cajal, log) = recorder.into_inner();
+        let replayed = Replayer::new(Cursor::new(log)).verify(true).replay().unwrap();
+        assert!(replayed.state_hash() == cajal.state_hash());
+        assert!(replayed.readout("out") == cajal.readout("out"));
+    }
+}
@@ -0,0 +1,362 @@
+use std::io::{self, Write};
+
+use grid::{Cell, CellType, Chromosome, Gate};
+use output::Region;
+use super::{Cajal, CajalError};
+
+const WHITE: [u8; 3] = [0xFF, 0xFF, 0xFF];
+const AXON: [u8; 3] = [0xF2, 0x5F, 0x5C];         // red
+const DENDRITE: [u8; 3] = [0x70, 0xC1, 0xB3];     // blue
+const BODY: [u8; 3] = [0x50, 0x51, 0x4F];         // brown
+const FIRING: [u8; 3] = [0xF9, 0xC2, 0x2E];       // yellow
+const CHARGED: [u8; 3] = [0xFA, 0xEB, 0xC3];      // pale yellow
+
+const GATES: [[u8; 3]; 4] = [[0x24, 0x7B, 0xA0],  // North
+                             [0x70, 0xC1, 0xB3],  // West
+                             [0xF2, 0x5F, 0x5C],  // South
+                             [0xF9, 0xC2, 0x2E]]; // East
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Layer {
+    CellType,
+    Signal,
+    Threshold,
+    Chromosome,
+    Gate,
+}
+
+pub fn color(cell: &Cell, layer: Layer) -> [u8; 3] {
+    let cell_type = cell.get_cell_type();
+
+    match layer {
+        Layer::CellType => {
+            if cell.get_signal() > cell.get_threshold() {
+                return FIRING;
+            } else if cell.get_signal() > 0 {
+                return CHARGED;
+            }
+
+            match cell_type {
+                CellType::Axon => AXON,
+                CellType::Dendrite => DENDRITE,
+                CellType::Body => BODY,
+                CellType::Empty => WHITE,
+            }
+        }
+        _ if cell_type == CellType::Empty => WHITE,
+        Layer::Signal => ramp(cell.get_signal(), FIRING),
+        Layer::Threshold => ramp(cell.get_threshold(), BODY),
+        Layer::Chromosome => {
+            // North, East and West each drive a channel, South darkens the whole colour
+            let c = cell.get_chromosome();
+            let scale = if c.contains(Chromosome::South) { 1 } else { 2 };
+            let channel = |on: bool| if on { 0x70 * scale } else { 0x18 * scale };
+            [channel(c.contains(Chromosome::North)),
+             channel(c.contains(Chromosome::East)),
+             channel(c.contains(Chromosome::West))]
+        }
+        Layer::Gate => {
+            match cell.get_gate() {
+                Gate::North => GATES[0],
+                Gate::West => GATES[1],
+                Gate::South => GATES[2],
+                Gate::East => GATES[3],
+            }
+        }
+    }
+}
+
+// Linear blend from white to `to` over the 6-bit range of a cell field
+fn ramp(value: u8, to: [u8; 3]) -> [u8; 3] {
+    let value = if value > 63 { 63 } else { value } as u32;
+    let mut rgb = [0u8; 3];
+    for i in 0..3 {
+        rgb[i] = (0xFF - ((0xFF - to[i] as u32) * value) / 63) as u8;
+    }
+    rgb
+}
+
+
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn from_cajal(cajal: &Cajal, layer: Layer) -> Image {
+        let mut image = Image::blank(cajal.width(), cajal.height());
+        for y in 0..cajal.height() {
+            for x in 0..cajal.width() {
+                image.set_pixel(x, y, color(cajal.get_cell(x, y), layer));
+            }
+        }
+        image
+    }
+
+    // A list of cells is drawn within its bounding box, anything not on the list is left
+    // white
+    pub fn from_region(cajal: &Cajal, region: &Region, layer: Layer) -> Result<Image, CajalError> {
+        try!(region.check(cajal.width(), cajal.height()));
+        let (left, top, width, height) = match *region {
+            Region::Rect { x, y, width, height } => (x, y, width, height),
+            Region::Cells(ref cells) if cells.is_empty() => (0, 0, 0, 0),
+            Region::Cells(ref cells) => {
+                let left = cells.iter().map(|c| c.0).min().unwrap_or(0);
+                let top = cells.iter().map(|c| c.1).min().unwrap_or(0);
+                let right = cells.iter().map(|c| c.0).max().unwrap_or(0);
+                let bottom = cells.iter().map(|c| c.1).max().unwrap_or(0);
+                (left, top, (right - left).saturating_add(1), (bottom - top).saturating_add(1))
+            }
+        };
+
+        let mut image = Image::blank(width, height);
+        for (x, y) in region.cells() {
+            image.set_pixel(x - left, y - top, color(cajal.get_cell(x, y), layer));
+        }
+        Ok(image)
+    }
+
+    fn blank(width: u32, height: u32) -> Image {
+        Image {
+            width: width,
+            height: height,
+            pixels: vec![0xFF; width as usize * height as usize * 3],
+        }
+    }
+
+    fn set_pixel(&mut self, x: u32, y: u32, rgb: [u8; 3]) {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        self.pixels[i..i + 3].copy_from_slice(&rgb);
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = (y as usize * self.width as usize + x as usize) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(write!(writer, "P6\n{} {}\n255\n", self.width, self.height));
+        writer.write_all(&self.pixels)
+    }
+
+    pub fn write_png<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        try!(writer.write_all(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]));
+
+        let mut header = Vec::with_capacity(13);
+        push_u32_be(&mut header, self.width);
+        push_u32_be(&mut header, self.height);
+        header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlacing
+        try!(write_chunk(writer, b"IHDR", &header));
+
+        // Every scanline is prefixed with filter type 0 (none)
+        let stride = self.width as usize * 3;
+        let mut raw = Vec::with_capacity((stride + 1) * self.height as usize);
+        for row in self.pixels.chunks(stride) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+        try!(write_chunk(writer, b"IDAT", &zlib_stored(&raw)));
+
+        write_chunk(writer, b"IEND", &[])
+    }
+}
+
+fn push_u32_be(buf: &mut Vec<u8>, value: u32) {
+    buf.push((value >> 24) as u8);
+    buf.push((value >> 16) as u8);
+    buf.push((value >> 8) as u8);
+    buf.push(value as u8);
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    push_u32_be(&mut chunk, data.len() as u32);
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    push_u32_be(&mut chunk, crc);
+    writer.write_all(&chunk)
+}
+
+// Wraps the data in a zlib stream of uncompressed deflate blocks.  Images are mostly
+// flat colour so this is wasteful, but it saves pulling in a compression dependency
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 65535 * 5 + 11);
+    out.push(0x78);
+    out.push(0x01);
+
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.push(len as u8);
+        out.push((len >> 8) as u8);
+        out.push(!len as u8);
+        out.push((!len >> 8) as u8);
+        out.extend_from_slice(block);
+    }
+
+    push_u32_be(&mut out, adler32(data));
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// Byte-wise CRC-32 of the reflected polynomial 0xEDB88320
+static CRC32_TABLE: [u32; 256] = [
+    0x00000000, 0x77073096, 0xee0e612c, 0x990951ba, 0x076dc419, 0x706af48f,
+    0xe963a535, 0x9e6495a3, 0x0edb8832, 0x79dcb8a4, 0xe0d5e91e, 0x97d2d988,
+    0x09b64c2b, 0x7eb17cbd, 0xe7b82d07, 0x90bf1d91, 0x1db71064, 0x6ab020f2,
+    0xf3b97148, 0x84be41de, 0x1adad47d, 0x6ddde4eb, 0xf4d4b551, 0x83d385c7,
+    0x136c9856, 0x646ba8c0, 0xfd62f97a, 0x8a65c9ec, 0x14015c4f, 0x63066cd9,
+    0xfa0f3d63, 0x8d080df5, 0x3b6e20c8, 0x4c69105e, 0xd56041e4, 0xa2677172,
+    0x3c03e4d1, 0x4b04d447, 0xd20d85fd, 0xa50ab56b, 0x35b5a8fa, 0x42b2986c,
+    0xdbbbc9d6, 0xacbcf940, 0x32d86ce3, 0x45df5c75, 0xdcd60dcf, 0xabd13d59,
+    0x26d930ac, 0x51de003a, 0xc8d75180, 0xbfd06116, 0x21b4f4b5, 0x56b3c423,
+    0xcfba9599, 0xb8bda50f, 0x2802b89e, 0x5f058808, 0xc60cd9b2, 0xb10be924,
+    0x2f6f7c87, 0x58684c11, 0xc1611dab, 0xb6662d3d, 0x76dc4190, 0x01db7106,
+    0x98d220bc, 0xefd5102a, 0x71b18589, 0x06b6b51f, 0x9fbfe4a5, 0xe8b8d433,
+    0x7807c9a2, 0x0f00f934, 0x9609a88e, 0xe10e9818, 0x7f6a0dbb, 0x086d3d2d,
+    0x91646c97, 0xe6635c01, 0x6b6b51f4, 0x1c6c6162, 0x856530d8, 0xf262004e,
+    0x6c0695ed, 0x1b01a57b, 0x8208f4c1, 0xf50fc457, 0x65b0d9c6, 0x12b7e950,
+    0x8bbeb8ea, 0xfcb9887c, 0x62dd1ddf, 0x15da2d49, 0x8cd37cf3, 0xfbd44c65,
+    0x4db26158, 0x3ab551ce, 0xa3bc0074, 0xd4bb30e2, 0x4adfa541, 0x3dd895d7,
+    0xa4d1c46d, 0xd3d6f4fb, 0x4369e96a, 0x346ed9fc, 0xad678846, 0xda60b8d0,
+    0x44042d73, 0x33031de5, 0xaa0a4c5f, 0xdd0d7cc9, 0x5005713c, 0x270241aa,
+    0xbe0b1010, 0xc90c2086, 0x5768b525, 0x206f85b3, 0xb966d409, 0xce61e49f,
+    0x5edef90e, 0x29d9c998, 0xb0d09822, 0xc7d7a8b4, 0x59b33d17, 0x2eb40d81,
+    0xb7bd5c3b, 0xc0ba6cad, 0xedb88320, 0x9abfb3b6, 0x03b6e20c, 0x74b1d29a,
+    0xead54739, 0x9dd277af, 0x04db2615, 0x73dc1683, 0xe3630b12, 0x94643b84,
+    0x0d6d6a3e, 0x7a6a5aa8, 0xe40ecf0b, 0x9309ff9d, 0x0a00ae27, 0x7d079eb1,
+    0xf00f9344, 0x8708a3d2, 0x1e01f268, 0x6906c2fe, 0xf762575d, 0x806567cb,
+    0x196c3671, 0x6e6b06e7, 0xfed41b76, 0x89d32be0, 0x10da7a5a, 0x67dd4acc,
+    0xf9b9df6f, 0x8ebeeff9, 0x17b7be43, 0x60b08ed5, 0xd6d6a3e8, 0xa1d1937e,
+    0x38d8c2c4, 0x4fdff252, 0xd1bb67f1, 0xa6bc5767, 0x3fb506dd, 0x48b2364b,
+    0xd80d2bda, 0xaf0a1b4c, 0x36034af6, 0x41047a60, 0xdf60efc3, 0xa867df55,
+    0x316e8eef, 0x4669be79, 0xcb61b38c, 0xbc66831a, 0x256fd2a0, 0x5268e236,
+    0xcc0c7795, 0xbb0b4703, 0x220216b9, 0x5505262f, 0xc5ba3bbe, 0xb2bd0b28,
+    0x2bb45a92, 0x5cb36a04, 0xc2d7ffa7, 0xb5d0cf31, 0x2cd99e8b, 0x5bdeae1d,
+    0x9b64c2b0, 0xec63f226, 0x756aa39c, 0x026d930a, 0x9c0906a9, 0xeb0e363f,
+    0x72076785, 0x05005713, 0x95bf4a82, 0xe2b87a14, 0x7bb12bae, 0x0cb61b38,
+    0x92d28e9b, 0xe5d5be0d, 0x7cdcefb7, 0x0bdbdf21, 0x86d3d2d4, 0xf1d4e242,
+    0x68ddb3f8, 0x1fda836e, 0x81be16cd, 0xf6b9265b, 0x6fb077e1, 0x18b74777,
+    0x88085ae6, 0xff0f6a70, 0x66063bca, 0x11010b5c, 0x8f659eff, 0xf862ae69,
+    0x616bffd3, 0x166ccf45, 0xa00ae278, 0xd70dd2ee, 0x4e048354, 0x3903b3c2,
+    0xa7672661, 0xd06016f7, 0x4969474d, 0x3e6e77db, 0xaed16a4a, 0xd9d65adc,
+    0x40df0b66, 0x37d83bf0, 0xa9bcae53, 0xdebb9ec5, 0x47b2cf7f, 0x30b5ffe9,
+    0xbdbdf21c, 0xcabac28a, 0x53b39330, 0x24b4a3a6, 0xbad03605, 0xcdd70693,
+    0x54de5729, 0x23d967bf, 0xb3667a2e, 0xc4614ab8, 0x5d681b02, 0x2a6f2b94,
+    0xb40bbe37, 0xc30c8ea1, 0x5a05df1b, 0x2d02ef8d,
+];
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{Image, Layer, adler32, crc32, color, AXON, FIRING, WHITE};
+    use grid::{Cell, CellType};
+    use {Cajal, CajalError, Region};
+
+    #[test]
+    fn checksums() {
+        assert!(crc32(b"IEND") == 0xAE426082);
+        assert!(adler32(b"Wikipedia") == 0x11E60398);
+    }
+
+    #[test]
+    fn cell_type_colors() {
+        let mut cell = Cell::new();
+        assert!(color(&cell, Layer::CellType) == WHITE);
+        assert!(color(&cell, Layer::Threshold) == WHITE);
+
+        cell.set_cell_type(CellType::Axon);
+        assert!(color(&cell, Layer::CellType) == AXON);
+
+        cell.set_signal(10);
+        cell.set_threshold(2);
+        assert!(color(&cell, Layer::CellType) == FIRING);
+
+        cell.set_signal(63);
+        assert!(color(&cell, Layer::Signal) == FIRING);
+    }
+
+    #[test]
+    fn write_images() {
+        let cajal = Cajal::new(1, 0.05, &[1, 2, 3, 4]);
+        let region = Region::Rect {
+            x: 10,
+            y: 20,
+            width: 30,
+            height: 40,
+        };
+        let image = Image::from_region(&cajal, &region, Layer::CellType).unwrap();
+        assert!(image.width() == 30 && image.height() == 40);
+        assert!(image.get_pixel(5, 7) == color(cajal.get_cell(15, 27), Layer::CellType));
+
+        let cells = Region::Cells(vec![(12, 3), (10, 5)]);
+        let sparse = Image::from_region(&cajal, &cells, Layer::Gate).unwrap();
+        assert!(sparse.width() == 3 && sparse.height() == 3);
+        assert!(sparse.get_pixel(2, 0) == color(cajal.get_cell(12, 3), Layer::Gate));
+        assert!(sparse.get_pixel(1, 1) == WHITE);
+
+        let mut ppm = Vec::new();
+        image.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n30 40\n255\n"));
+        assert!(ppm.len() == 13 + 30 * 40 * 3);
+
+        let mut png = Vec::new();
+        Image::from_cajal(&cajal, Layer::Gate).write_png(&mut png).unwrap();
+        assert!(png.starts_with(&[0x89, b'P', b'N', b'G']));
+        assert!(png.ends_with(&[b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+    }
+
+    #[test]
+    fn region_out_of_bounds() {
+        let cajal = Cajal::new(1, 0.05, &[1, 2, 3, 4]);
+        let rect = |x, width| {
+            Region::Rect {
+                x: x,
+                y: 0,
+                width: width,
+                height: 10,
+            }
+        };
+        match Image::from_region(&cajal, &rect(200, 100), Layer::Signal) {
+            Err(CajalError::OutOfBounds { x: 299, y: 9, .. }) => {}
+            _ => panic!("expected out of bounds"),
+        }
+        match Image::from_region(&cajal, &rect(200, u32::max_value()), Layer::Signal) {
+            Err(CajalError::Overflow(_)) => {}
+            _ => panic!("expected an overflow"),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+// What a single grow or signal step did, summed over all pages.  Counts that belong to
+// the other kind of step stay zero
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepReport {
+    // Cells the next step starts from: those that just grew, or that now hold signal
+    pub active_cells: u32,
+    pub bodies_grown: u32,
+    pub axons_grown: u32,
+    pub dendrites_grown: u32,
+    // Growth that stayed on its page, and growth that had to cross over to another one
+    pub local_changes: u32,
+    pub remote_changes: u32,
+    // Signals that reached a cell, and bodies that crossed threshold
+    pub signals_delivered: u32,
+    pub bodies_fired: u32,
+    // Pages that had active cells or were sent changes or signals
+    pub pages_touched: u32,
+    // Wall time of the parallel pass over the pages, of routing what crosses between
+    // pages, and of applying the results
+    pub process_time: Duration,
+    pub route_time: Duration,
+    pub update_time: Duration,
+}
+
+impl StepReport {
+    pub fn grown(&self) -> u32 {
+        self.bodies_grown + self.axons_grown + self.dendrites_grown
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.process_time + self.route_time + self.update_time
+    }
+}
+
+impl Default for StepReport {
+    fn default() -> StepReport {
+        StepReport {
+            active_cells: 0,
+            bodies_grown: 0,
+            axons_grown: 0,
+            dendrites_grown: 0,
+            local_changes: 0,
+            remote_changes: 0,
+            signals_delivered: 0,
+            bodies_fired: 0,
+            pages_touched: 0,
+            process_time: Duration::new(0, 0),
+            route_time: Duration::new(0, 0),
+            update_time: Duration::new(0, 0),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::cmp;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &'static [u8; 4] = b"CAJL";
+pub const VERSION: u32 = 1;
+
+// Lengths come from the file, so nothing is allocated for more than this many entries
+// ahead of actually reading them
+const CHUNK: usize = 4096;
+
+pub trait Snapshot: Sized {
+    fn save<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn load<R: Read>(reader: &mut R) -> io::Result<Self>;
+}
+
+pub fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub fn write_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    try!(writer.write_all(MAGIC));
+    write_u32(writer, VERSION)
+}
+
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut magic = [0u8; 4];
+    try!(reader.read_exact(&mut magic));
+    if &magic != MAGIC {
+        return Err(invalid_data("not a cajal snapshot"));
+    }
+
+    let version = try!(read_u32(reader));
+    if version != VERSION {
+        debug!("Snapshot version {}, expected {}", version, VERSION);
+        return Err(invalid_data("unsupported snapshot version"));
+    }
+    Ok(version)
+}
+
+pub fn write_u8<W: Write>(writer: &mut W, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+pub fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    try!(reader.read_exact(&mut buf));
+    Ok(buf[0])
+}
+
+pub fn write_bool<W: Write>(writer: &mut W, value: bool) -> io::Result<()> {
+    write_u8(writer, value as u8)
+}
+
+pub fn read_bool<R: Read>(reader: &mut R) -> io::Result<bool> {
+    match try!(read_u8(reader)) {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid_data("invalid boolean")),
+    }
+}
+
+pub fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])
+}
+
+pub fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    try!(reader.read_exact(&mut buf));
+    Ok((buf[0] as u32) | ((buf[1] as u32) << 8) | ((buf[2] as u32) << 16) |
+       ((buf[3] as u32) << 24))
+}
+
+pub fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    try!(write_u32(writer, value as u32));
+    write_u32(writer, (value >> 32) as u32)
+}
+
+pub fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let low = try!(read_u32(reader)) as u64;
+    let high = try!(read_u32(reader)) as u64;
+    Ok(low | (high << 32))
+}
+
+// Absolute cell positions, x then y then z
+pub fn write_position<W: Write>(writer: &mut W, (x, y, z): (u32, u32, u32)) -> io::Result<()> {
+    try!(write_u32(writer, x));
+    try!(write_u32(writer, y));
+    write_u32(writer, z)
+}
+
+pub fn read_position<R: Read>(reader: &mut R) -> io::Result<(u32, u32, u32)> {
+    let x = try!(read_u32(reader));
+    let y = try!(read_u32(reader));
+    Ok((x, y, try!(read_u32(reader))))
+}
+
+// Floats are stored bit-for-bit, density decides how many bodies each page places
+pub fn write_f32<W: Write>(writer: &mut W, value: f32) -> io::Result<()> {
+    write_u32(writer, value.to_bits())
+}
+
+pub fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+    Ok(f32::from_bits(try!(read_u32(reader))))
+}
+
+// Bulk variants, so a page worth of cells goes out in a single write
+pub fn write_u32_slice<W: Write>(writer: &mut W, values: &[u32]) -> io::Result<()> {
+    try!(write_u32(writer, values.len() as u32));
+    let mut buf = Vec::with_capacity(values.len() * 4);
+    for value in values {
+        buf.push(*value as u8);
+        buf.push((*value >> 8) as u8);
+        buf.push((*value >> 16) as u8);
+        buf.push((*value >> 24) as u8);
+    }
+    writer.write_all(&buf)
+}
+
+// Read a chunk at a time, so a corrupt length runs out of input instead of memory
+pub fn read_u32_vec<R: Read>(reader: &mut R) -> io::Result<Vec<u32>> {
+    let len = try!(read_u32(reader)) as usize;
+    let mut values = Vec::with_capacity(cmp::min(len, CHUNK));
+    let mut buf = vec![0u8; cmp::min(len, CHUNK) * 4];
+    while values.len() < len {
+        let n = cmp::min(len - values.len(), CHUNK);
+        try!(reader.read_exact(&mut buf[..n * 4]));
+        values.extend(buf[..n * 4]
+                          .chunks(4)
+                          .map(|b| (b[0] as u32) | ((b[1] as u32) << 8) | ((b[2] as u32) << 16) |
+                                   ((b[3] as u32) << 24)));
+    }
+    Ok(values)
+}
+
+// Capacity to reserve for `len` entries that are yet to be read
+pub fn capacity(len: u32) -> usize {
+    cmp::min(len as usize, CHUNK)
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn roundtrip_primitives() {
+        let mut buf = Vec::new();
+        write_header(&mut buf).unwrap();
+        write_u8(&mut buf, 7).unwrap();
+        write_bool(&mut buf, true).unwrap();
+        write_u32(&mut buf, 0xdeadbeef).unwrap();
+        write_u64(&mut buf, 0x0123456789abcdef).unwrap();
+        write_f32(&mut buf, 0.05).unwrap();
+        write_u32_slice(&mut buf, &[1, 2, 3, 0xffffffff]).unwrap();
+
+        let mut reader = Cursor::new(buf);
+        assert!(read_header(&mut reader).unwrap() == VERSION);
+        assert!(read_u8(&mut reader).unwrap() == 7);
+        assert!(read_bool(&mut reader).unwrap() == true);
+        assert!(read_u32(&mut reader).unwrap() == 0xdeadbeef);
+        assert!(read_u64(&mut reader).unwrap() == 0x0123456789abcdef);
+        assert!(read_f32(&mut reader).unwrap() == 0.05);
+        assert!(read_u32_vec(&mut reader).unwrap() == vec![1, 2, 3, 0xffffffff]);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut reader = Cursor::new(b"NOPE\x01\x00\x00\x00".to_vec());
+        assert!(read_header(&mut reader).is_err());
+    }
+
+    #[test]
+    fn truncated_vec() {
+        let mut buf = Vec::new();
+        write_u32(&mut buf, 0xffffffff).unwrap();
+        write_u32(&mut buf, 1).unwrap();
+        assert!(read_u32_vec(&mut Cursor::new(buf)).is_err());
+        assert!(capacity(0xffffffff) == 4096);
+    }
+}
@@ -0,0 +1,390 @@
+use growth::GrowthRule;
+use model::NeuronModel;
+use super::{Cajal, CajalError};
+
+// Signal applied through `set_input` before the given step of a trial
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stimulus {
+    pub step: u32,
+    pub x: u32,
+    pub y: u32,
+    pub signal: u8,
+}
+
+// Whether an output should fire during at least one of the steps in [from, to)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Response {
+    pub output: usize,
+    pub from: u32,
+    pub to: u32,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    pub steps: u32,
+    pub stimuli: Vec<Stimulus>,
+    pub responses: Vec<Response>,
+}
+
+// A benchmark: trials of input patterns, each run on a fresh copy of the grid, and the
+// output cells their responses are read from
+pub trait Task {
+    fn outputs(&self) -> Vec<(u32, u32)>;
+    fn trials(&self) -> Vec<Trial>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Score {
+    pub correct: u32,
+    pub total: u32,
+}
+
+impl Score {
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            0 => 0.0,
+            total => self.correct as f32 / total as f32,
+        }
+    }
+}
+
+// Runs every trial of `task` against a copy of `cajal` and counts the responses it got
+// right.  Fails if the task refers to cells outside of the grid or to outputs it doesn't
+// have
+pub fn evaluate<M, G, T>(cajal: &Cajal<M, G>, task: &T) -> Result<Score, CajalError>
+    where M: NeuronModel + Clone,
+          G: GrowthRule + Clone,
+          T: Task
+{
+    let outputs = task.outputs();
+    for &(x, y) in &outputs {
+        try!(cajal.try_get_cell(x, y));
+    }
+    let trials = task.trials();
+    for r in trials.iter().flat_map(|trial| trial.responses.iter()) {
+        if r.output >= outputs.len() {
+            return Err(CajalError::Task(format!("response from output {} of {}",
+                                                r.output,
+                                                outputs.len())));
+        }
+    }
+
+    let mut score = Score {
+        correct: 0,
+        total: 0,
+    };
+    for trial in trials {
+        let mut cajal = cajal.clone();
+
+        // activity[step][output]
+        let mut activity = Vec::with_capacity(trial.steps as usize);
+        for step in 0..trial.steps {
+            for s in trial.stimuli.iter().filter(|s| s.step == step) {
+                try!(cajal.try_set_input(s.x, s.y, s.signal));
+            }
+            cajal.signal_step();
+            activity.push(outputs.iter()
+                                 .map(|&(x, y)| cajal.is_firing(x, y))
+                                 .collect::<Vec<bool>>());
+        }
+
+        for r in &trial.responses {
+            let active = (r.from..r.to)
+                             .filter(|step| *step < trial.steps)
+                             .any(|step| activity[step as usize][r.output]);
+            if active == r.active {
+                score.correct += 1;
+            }
+            score.total += 1;
+        }
+    }
+
+    Ok(score)
+}
+
+
+// Two inputs and one output, which should respond when exactly one input is stimulated
+pub struct Xor {
+    pub inputs: [(u32, u32); 2],
+    pub output: (u32, u32),
+    pub signal: u8,
+    pub steps: u32,
+}
+
+impl Xor {
+    pub fn new(inputs: [(u32, u32); 2], output: (u32, u32)) -> Xor {
+        Xor {
+            inputs: inputs,
+            output: output,
+            signal: 63,
+            steps: 10,
+        }
+    }
+}
+
+impl Task for Xor {
+    fn outputs(&self) -> Vec<(u32, u32)> {
+        vec![self.output]
+    }
+
+    fn trials(&self) -> Vec<Trial> {
+        [(false, false), (false, true), (true, false), (true, true)]
+            .iter()
+            .map(|&(a, b)| {
+                Trial {
+                    steps: self.steps,
+                    stimuli: self.inputs
+                                 .iter()
+                                 .zip(&[a, b])
+                                 .filter(|&(_, on)| *on)
+                                 .map(|(&(x, y), _)| {
+                                     Stimulus {
+                                         step: 0,
+                                         x: x,
+                                         y: y,
+                                         signal: self.signal,
+                                     }
+                                 })
+                                 .collect(),
+                    responses: vec![Response {
+                                        output: 0,
+                                        from: 0,
+                                        to: self.steps,
+                                        active: a != b,
+                                    }],
+                }
+            })
+            .collect()
+    }
+}
+
+// Each pattern stimulates a set of input cells and should light up its own output, and
+// none of the others
+pub struct Patterns {
+    pub inputs: Vec<(u32, u32)>,
+    pub patterns: Vec<Vec<bool>>,
+    pub outputs: Vec<(u32, u32)>,
+    pub signal: u8,
+    pub steps: u32,
+}
+
+impl Patterns {
+    // There has to be an output for every pattern, and a value for every input in each
+    pub fn new(inputs: Vec<(u32, u32)>,
+               patterns: Vec<Vec<bool>>,
+               outputs: Vec<(u32, u32)>)
+               -> Result<Patterns, CajalError> {
+        if patterns.len() != outputs.len() {
+            return Err(CajalError::Task(format!("{} patterns for {} outputs",
+                                                patterns.len(),
+                                                outputs.len())));
+        }
+        if let Some(p) = patterns.iter().find(|p| p.len() != inputs.len()) {
+            return Err(CajalError::Task(format!("a pattern of {} values for {} inputs",
+                                                p.len(),
+                                                inputs.len())));
+        }
+
+        Ok(Patterns {
+            inputs: inputs,
+            patterns: patterns,
+            outputs: outputs,
+            signal: 63,
+            steps: 10,
+        })
+    }
+}
+
+impl Task for Patterns {
+    fn outputs(&self) -> Vec<(u32, u32)> {
+        self.outputs.clone()
+    }
+
+    fn trials(&self) -> Vec<Trial> {
+        self.patterns
+            .iter()
+            .enumerate()
+            .map(|(i, pattern)| {
+                Trial {
+                    steps: self.steps,
+                    stimuli: self.inputs
+                                 .iter()
+                                 .zip(pattern)
+                                 .filter(|&(_, on)| *on)
+                                 .map(|(&(x, y), _)| {
+                                     Stimulus {
+                                         step: 0,
+                                         x: x,
+                                         y: y,
+                                         signal: self.signal,
+                                     }
+                                 })
+                                 .collect(),
+                    responses: (0..self.outputs.len())
+                                   .map(|output| {
+                                       Response {
+                                           output: output,
+                                           from: 0,
+                                           to: self.steps,
+                                           active: output == i,
+                                       }
+                                   })
+                                   .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+// A pulse into the input should reach the output exactly `delay` steps later, give or
+// take `tolerance`, and the output should stay quiet without one
+pub struct DelayLine {
+    pub input: (u32, u32),
+    pub output: (u32, u32),
+    pub delay: u32,
+    pub tolerance: u32,
+    pub signal: u8,
+}
+
+impl DelayLine {
+    pub fn new(input: (u32, u32), output: (u32, u32), delay: u32) -> DelayLine {
+        DelayLine {
+            input: input,
+            output: output,
+            delay: delay,
+            tolerance: 0,
+            signal: 63,
+        }
+    }
+}
+
+impl Task for DelayLine {
+    fn outputs(&self) -> Vec<(u32, u32)> {
+        vec![self.output]
+    }
+
+    fn trials(&self) -> Vec<Trial> {
+        let early = self.delay.saturating_sub(self.tolerance);
+        let late = self.delay + self.tolerance + 1;
+        let quiet = Response {
+            output: 0,
+            from: 0,
+            to: late,
+            active: false,
+        };
+
+        vec![Trial {
+                 steps: late,
+                 stimuli: vec![Stimulus {
+                                   step: 0,
+                                   x: self.input.0,
+                                   y: self.input.1,
+                                   signal: self.signal,
+                               }],
+                 responses: vec![Response { to: early, ..quiet },
+                                 Response {
+                                     from: early,
+                                     active: true,
+                                     ..quiet
+                                 }],
+             },
+             Trial {
+                 steps: late,
+                 stimuli: Vec::new(),
+                 responses: vec![quiet],
+             }]
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{evaluate, DelayLine, Patterns, Response, Task, Trial, Xor};
+    use {Cajal, CajalBuilder, CajalError, CellType, Gate};
+
+    fn empty() -> Cajal {
+        CajalBuilder::new().size(1).density(0.0).build().unwrap()
+    }
+
+    #[test]
+    fn empty_grid_never_responds() {
+        let cajal = empty();
+
+        let xor = evaluate(&cajal, &Xor::new([(10, 10), (20, 10)], (15, 20))).unwrap();
+        assert!(xor.correct == 2 && xor.total == 4);
+
+        let patterns = Patterns::new(vec![(10, 10), (20, 10)],
+                                     vec![vec![true, false], vec![false, true]],
+                                     vec![(10, 20), (20, 20)])
+                           .unwrap();
+        let patterns = evaluate(&cajal, &patterns).unwrap();
+        assert!(patterns.correct == 2 && patterns.total == 4);
+        assert!(patterns.fraction() == 0.5);
+
+        let delay = evaluate(&cajal, &DelayLine::new((10, 10), (50, 10), 5)).unwrap();
+        assert!(delay.correct == 2 && delay.total == 3);
+    }
+
+    #[test]
+    fn input_is_output() {
+        // An axon fires in the step it gets input, even with nowhere to pass it on to
+        let mut cajal = empty();
+        {
+            let cell = cajal.grid.get_mut_cell(10, 10);
+            cell.set_cell_type(CellType::Axon);
+            cell.set_gate(Gate::West);
+            cell.set_threshold(1);
+            cell.set_strength(5);
+        }
+        let score = evaluate(&cajal, &DelayLine::new((10, 10), (10, 10), 0)).unwrap();
+        assert!(score.correct == 3 && score.total == 3);
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        match evaluate(&empty(), &DelayLine::new((10, 10), (300, 10), 2)) {
+            Err(CajalError::OutOfBounds { .. }) => {}
+            _ => panic!("expected out of bounds"),
+        }
+    }
+
+    // Expects a response from a second output, but only has one
+    struct UnknownOutput;
+
+    impl Task for UnknownOutput {
+        fn outputs(&self) -> Vec<(u32, u32)> {
+            vec![(10, 20)]
+        }
+
+        fn trials(&self) -> Vec<Trial> {
+            vec![Trial {
+                     steps: 2,
+                     stimuli: Vec::new(),
+                     responses: vec![Response {
+                                         output: 1,
+                                         from: 0,
+                                         to: 2,
+                                         active: true,
+                                     }],
+                 }]
+        }
+    }
+
+    #[test]
+    fn invalid_tasks() {
+        match Patterns::new(vec![(10, 10)], vec![vec![true]], vec![(10, 20), (20, 20)]) {
+            Err(CajalError::Task(_)) => {}
+            _ => panic!("expected a pattern without an output"),
+        }
+        match Patterns::new(vec![(10, 10)], vec![vec![true, false]], vec![(10, 20)]) {
+            Err(CajalError::Task(_)) => {}
+            _ => panic!("expected a pattern that doesn't match the inputs"),
+        }
+
+        match evaluate(&empty(), &UnknownOutput) {
+            Err(CajalError::Task(_)) => {}
+            _ => panic!("expected an unknown output"),
+        }
+    }
+}